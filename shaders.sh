@@ -1,3 +1,5 @@
 #!/bin/sh
 glslc -o src/shader.vert.spv src/shader.vert
 glslc -o src/shader.frag.spv src/shader.frag
+glslc -o src/overlay.vert.spv src/overlay.vert
+glslc -o src/overlay.frag.spv src/overlay.frag
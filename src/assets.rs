@@ -1,18 +1,42 @@
 use bumpalo::{collections::Vec as BumpVec, Bump};
 use std::collections::{BTreeSet, HashMap};
 
-use crate::{Component, Library, Pixel, TextureView, Wgpu, WgpuUpload};
+use crate::{
+    Animation, Component, ImageOffset, ImageSize, Library, MaterialId, Pixel, TextureView, Wgpu,
+    WgpuUpload,
+};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct AssetKey(pub hecs::Entity);
 
 pub struct Assets {
     //TODO: make private??
     pub world: hecs::World,
     from_path: HashMap<String, AssetKey>,
+    placeholder: AssetKey,
+    /// Texture atlases with their free space, kept to pack assets of later maps.
+    atlases: Vec<Atlas>,
     bump: Bump,
 }
 
+struct Atlas {
+    material_id: MaterialId,
+    allocator: guillotiere::SimpleAtlasAllocator,
+}
+
+/// Asset that could not be loaded, see `Assets::failed`.
+#[derive(Debug, Clone)]
+pub struct FailedAsset {
+    pub path: String,
+    pub error: String,
+    /// Number of sprites of the map that use the asset.
+    pub usage: u32,
+}
+
+/// Size of the placeholder checker, one hex.
+const PLACEHOLDER_SIZE: (u32, u32) = (32, 16);
+const PLACEHOLDER_CELL: u32 = 4;
+
 #[derive(Debug)]
 struct AssetPath(String);
 
@@ -47,13 +71,24 @@ struct AssetStatistics {
     pub upserted: u32,
 }
 
+/// Atlas placement of every frame, inserted for assets with more than one frame.
+#[derive(Debug)]
+pub struct AnimationViews {
+    pub fps: u16,
+    pub frames: Vec<(TextureView, ImageOffset)>,
+}
+
 //struct AssetLoader;
 
 impl Assets {
     pub fn new() -> Self {
+        let mut world = hecs::World::new();
+        let placeholder = AssetKey(world.spawn(placeholder_components()));
         Self {
-            world: hecs::World::new(),
+            world,
             from_path: HashMap::new(),
+            placeholder,
+            atlases: vec![],
             bump: Bump::with_capacity(100 * 1024),
         }
     }
@@ -92,6 +127,7 @@ impl Assets {
         self.from_path.insert(path.to_owned(), key);
         key
     }
+    /// Loads assets added since the last call, failed ones are not retried.
     pub fn load(&mut self, library: &Library) {
         let Self {
             world, ref bump, ..
@@ -100,6 +136,7 @@ impl Assets {
             world
                 .query::<(&AssetPath, &mut AssetLoader)>()
                 .iter()
+                .filter(|(_, (_, asset_loader))| !asset_loader.status.is_error())
                 .flat_map(|(entity, (path, asset_loader))| {
                     //library.retriever.get_rgba(&path.0).ok().map(|raw| (entity, raw))
                     match (asset_loader.load)(library, &path.0, bump) {
@@ -135,74 +172,158 @@ impl Assets {
         }
         self.bump.reset();
     }
+    /// Uploads assets that are not on the GPU yet. They go into free space of the atlases
+    /// made by earlier calls first, a new atlas is only made for what doesn't fit there.
     pub fn sized_upload(&mut self, wgpu: &mut Wgpu) {
-        let Self {
-            world, ref bump, ..
-        } = self;
+        let Self { world, atlases, .. } = self;
 
         //const TEXTURE_MAX_SIZE: euclid::Size2D<u32, Pixel> = euclid::size2(4096, 4096);
         const TEXTURE_MAX_SIZE: euclid::Size2D<u32, Pixel> = euclid::size2(8192, 8192);
         use std::cmp::Reverse;
-        let mut total_size: euclid::Size2D<u32, Pixel> = euclid::size2(0, 0);
+        let mut remaining_area = 0u64;
         let sorted: BTreeSet<_> = world
-            .query::<(&crate::ImageSize, &AssetStatistics)>()
+            .query::<(&crate::ImageSize, &AssetStatistics, Option<&Animation>)>()
             .without::<crate::TextureView>()
             .iter()
-            .map(|(entity, (size, usage))| {
-                let size_u32 = size.0.to_u32();
+            .map(|(entity, (size, usage, animation))| {
+                let mut sizes = vec![size.0.to_u32()];
+                if let Some(animation) = animation {
+                    sizes.extend(
+                        animation
+                            .frames
+                            .iter()
+                            .map(|frame| euclid::size2(frame.image.width(), frame.image.height())),
+                    );
+                }
+                let (size_u32, _) = layout_frames(&sizes, TEXTURE_MAX_SIZE.width);
                 assert!(size_u32.greater_than(TEXTURE_MAX_SIZE).none());
-                total_size += size_u32;
+                remaining_area += size_u32.area() as u64;
                 (
                     Reverse(usage.upserted),
-                    Reverse(size.0.height),
-                    Reverse(size.0.width),
+                    Reverse(size_u32.height),
+                    Reverse(size_u32.width),
                     entity,
                 )
             })
             .collect();
 
-        if sorted.is_empty() {
-            return;
-        }
-        let mut iter = sorted.into_iter().peekable();
-
-        'new_atlas: loop {
-            let atlas_width = total_size
-                .width
-                .min(TEXTURE_MAX_SIZE.width)
-                .next_power_of_two();
-            let atlas_height = total_size
-                .height
-                .min(TEXTURE_MAX_SIZE.height)
-                .next_power_of_two();
-            let atlas_size = euclid::size2(atlas_width, atlas_height);
-            let mut atlas =
-                guillotiere::SimpleAtlasAllocator::new(atlas_size.to_untyped().to_i32());
-            let material_id = wgpu.create_material(atlas_size);
-
-            'new_entity: loop {
-                if let Some((_, Reverse(height), Reverse(width), entity)) = iter.peek() {
-                    if let Some(rect) = atlas.allocate(euclid::size2(*width, *height).to_i32()) {
+        for (_, Reverse(height), Reverse(width), entity) in sorted {
+            let size = euclid::size2(width, height).to_i32();
+            let free = atlases.iter_mut().find_map(|atlas| {
+                let rect = atlas.allocator.allocate(size)?;
+                Some((atlas.material_id, rect))
+            });
+            let (material_id, rect) = match free {
+                Some(free) => free,
+                None => {
+                    // Square atlas for everything still waiting, but at least this asset.
+                    let side = ((remaining_area as f64).sqrt().ceil() as u32)
+                        .max(width)
+                        .max(height)
+                        .next_power_of_two()
+                        .min(TEXTURE_MAX_SIZE.width);
+                    let atlas_size = euclid::size2(side, side);
+                    let mut allocator =
+                        guillotiere::SimpleAtlasAllocator::new(atlas_size.to_untyped().to_i32());
+                    let material_id = wgpu.create_material(atlas_size);
+                    let rect = allocator
+                        .allocate(size)
+                        .expect("asset fits into an empty atlas");
+                    atlases.push(Atlas {
+                        material_id,
+                        allocator,
+                    });
+                    (material_id, rect)
+                }
+            };
+            remaining_area -= (width * height) as u64;
+
+            let (fps, views) = {
+                let image = world.get::<image::RgbaImage>(entity).unwrap();
+                let offset = world
+                    .get::<ImageOffset>(entity)
+                    .map(|offset| *offset)
+                    .unwrap_or_default();
+                let animation = world.get::<Animation>(entity).ok();
+                let fps = animation.as_ref().map(|animation| animation.fps);
+                let mut frames = vec![(&*image, offset)];
+                if let Some(animation) = &animation {
+                    frames.extend(animation.frames.iter().map(|frame| {
+                        let offset = ImageOffset {
+                            x: frame.offset_x,
+                            y: frame.offset_y,
+                        };
+                        (&frame.image, offset)
+                    }));
+                }
+                let sizes: Vec<_> = frames
+                    .iter()
+                    .map(|(image, _)| euclid::size2(image.width(), image.height()))
+                    .collect();
+                let (_, positions) = layout_frames(&sizes, TEXTURE_MAX_SIZE.width);
+                let views: Vec<_> = frames
+                    .iter()
+                    .zip(positions)
+                    .map(|((image, offset), position)| {
+                        let min = euclid::point2(
+                            (rect.min.x as u32 + position.x) as u16,
+                            (rect.min.y as u32 + position.y) as u16,
+                        );
+                        let max = min + euclid::vec2(image.width() as u16, image.height() as u16);
                         let view = TextureView {
                             material_id,
-                            rect: rect.cast().cast_unit(),
+                            rect: euclid::Box2D::new(min, max),
                         };
-                        {
-                            let image = world.get::<image::RgbaImage>(*entity).unwrap();
-                            wgpu.upload_texture(view, &image);
-                        }
-                        world.insert_one(*entity, view).unwrap();
-                        let _ = iter.next();
-                    } else {
-                        break 'new_entity;
-                    }
-                } else {
-                    break 'new_atlas;
-                }
+                        wgpu.upload_texture(view, image);
+                        (view, *offset)
+                    })
+                    .collect();
+                (fps, views)
+            };
+            world.insert_one(entity, views[0].0).unwrap();
+            if let (Some(fps), true) = (fps, views.len() > 1) {
+                world
+                    .insert_one(entity, AnimationViews { fps, frames: views })
+                    .unwrap();
             }
         }
-
-        //self.bump.reset();
+    }
+    pub fn path(&self, key: AssetKey) -> Option<String> {
+        self.world
+            .get::<AssetPath>(key.0)
+            .ok()
+            .map(|path| path.0.clone())
+    }
+    /// Magenta checker drawn in place of assets that failed to load.
+    pub fn placeholder(&self) -> AssetKey {
+        self.placeholder
+    }
+    pub fn is_failed(&self, key: AssetKey) -> bool {
+        matches!(
+            self.world
+                .get::<AssetLoader>(key.0)
+                .map(|loader| loader.status.is_error()),
+            Ok(true)
+        )
+    }
+    /// Assets of `usage`, sprite counts by asset, that failed to load, most used first.
+    pub fn failed(&self, usage: &HashMap<AssetKey, u32>) -> Vec<FailedAsset> {
+        let mut failed: Vec<_> = usage
+            .iter()
+            .filter_map(|(key, usage)| {
+                let loader = self.world.get::<AssetLoader>(key.0).ok()?;
+                match &loader.status {
+                    AssetLoaderStatus::Error(error) => Some(FailedAsset {
+                        path: self.path(*key)?,
+                        error: error.clone(),
+                        usage: *usage,
+                    }),
+                    _ => None,
+                }
+            })
+            .collect();
+        failed.sort_by(|a, b| b.usage.cmp(&a.usage).then_with(|| a.path.cmp(&b.path)));
+        failed
     }
     pub fn _get<T: hecs::Component>(&self, key: AssetKey) -> Option<hecs::Ref<T>> {
         self.world.get(key.0).ok()
@@ -215,6 +336,61 @@ impl Assets {
 
     }*/
 }
+impl AssetLoaderStatus {
+    fn is_error(&self) -> bool {
+        matches!(self, AssetLoaderStatus::Error(_))
+    }
+}
+
+fn placeholder_components() -> (
+    AssetPath,
+    AssetStatistics,
+    ImageSize,
+    ImageOffset,
+    image::RgbaImage,
+) {
+    let (width, height) = PLACEHOLDER_SIZE;
+    let image = image::RgbaImage::from_fn(width, height, |x, y| {
+        if (x / PLACEHOLDER_CELL + y / PLACEHOLDER_CELL) % 2 == 0 {
+            image::Rgba([255, 0, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        }
+    });
+    (
+        AssetPath("#placeholder".into()),
+        AssetStatistics { upserted: 0 },
+        ImageSize(euclid::size2(width as u16, height as u16)),
+        // centered on the sprite anchor
+        ImageOffset {
+            x: -(width as i16) / 2,
+            y: -(height as i16) / 2,
+        },
+        image,
+    )
+}
+
+/// Places animation frames left to right, wrapping rows at `max_width`.
+fn layout_frames(
+    sizes: &[euclid::Size2D<u32, Pixel>],
+    max_width: u32,
+) -> (euclid::Size2D<u32, Pixel>, Vec<euclid::Point2D<u32, Pixel>>) {
+    let mut positions = Vec::with_capacity(sizes.len());
+    let (mut x, mut y, mut row_height, mut width) = (0, 0, 0, 0);
+    for size in sizes {
+        if x > 0 && x + size.width > max_width {
+            y += row_height;
+            x = 0;
+            row_height = 0;
+        }
+        positions.push(euclid::point2(x, y));
+        x += size.width;
+        width = width.max(x);
+        row_height = row_height.max(size.height);
+    }
+    (euclid::size2(width, y + row_height), positions)
+}
+
 /*
 const fn log2(mut x: usize) -> usize {
     let mut res = 0;
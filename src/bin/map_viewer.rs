@@ -1,14 +1,19 @@
-use relievo::State;
+use relievo::{RenderOptions, State};
 
 fn main() {
     #[cfg(not(target_arch = "wasm32"))]
     {
         tracing_subscriber::fmt::init();
-        let state = futures::executor::block_on(State::new());
-        let map = std::env::args()
-            .nth(1)
-            .unwrap_or_else(|| state.config.open_map.clone());
-        state.show_map(&map);
+        let result = futures::executor::block_on(State::new()).and_then(|state| {
+            let map = std::env::args()
+                .nth(1)
+                .unwrap_or_else(|| state.config.open_map.clone());
+            state.show_map(&map, &RenderOptions::default())
+        });
+        if let Err(err) = result {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
     }
     #[cfg(target_arch = "wasm32")]
     {
@@ -1,12 +1,296 @@
-use relievo::State;
+use relievo::{
+    AnimationTime, Bounds, Error, Geometry, Layer, Layers, MapDiagnostics, RenderOptions,
+    SpriteSidecar, State,
+};
+use std::path::Path;
 
-async fn run() {
-    let mut state = State::new().await;
-    let map = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| state.config.open_map.clone());
-    let output = format!("{}.png", &map);
-    state.render_map(&map, &output).await;
+const USAGE: &str = "\
+Usage: render_map [OPTIONS] [MAP]
+       render_map [OPTIONS] --batch DIR|PATTERN
+
+Renders MAP, or the map from config.toml, to MAP.png.
+
+Options:
+  --roofs, --no-roofs          show or hide roofs
+  --layers NAME,...            draw only these layers
+  --hide NAME,...              hide these layers
+  --frame N, --time SECONDS    animation moment
+  --grid, --passability, --mapper
+                               overlays, they show the overlays layer
+                               even if --layers or --hide leave it out
+  --geometry hex|square        map geometry
+  --header-bounds              bounds from the map header
+  --crop X0,Y0,X1,Y1           bounds in hexes
+  --pixels X0,Y0,X1,Y1         bounds in map pixels
+  --padding N                  pixels around the bounds
+  --scale F, --size WxH        output zoom and image size
+  --no-light, --ambient F, --hour HH:MM
+                               lighting
+  --sprites json|csv           write the drawn sprites next to the PNG
+  --tiles DIR                  write a z/x/y tile pyramid instead of a PNG
+  --batch DIR|PATTERN          render every matching .fomap file
+";
+
+struct Args {
+    map: Option<String>,
+    /// Directory or file name pattern of maps to render with one loaded library.
+    batch: Option<String>,
+    /// Directory for a tile pyramid instead of a single PNG.
+    tiles: Option<String>,
+    options: RenderOptions,
+}
+
+/// Error message for a bad command line.
+type ArgResult<T> = Result<T, String>;
+
+fn parse_args() -> ArgResult<Args> {
+    let mut args = Args {
+        map: None,
+        batch: None,
+        tiles: None,
+        options: RenderOptions::default(),
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--roofs" => args.options.layers.set(Layer::Roofs, true),
+            "--no-roofs" => args.options.layers.set(Layer::Roofs, false),
+            "--layers" => {
+                args.options.layers = layers(&arg, iter.next())?
+                    .into_iter()
+                    .fold(Layers::none(), |layers, layer| layers.with(layer, true))
+            }
+            "--hide" => {
+                for layer in layers(&arg, iter.next())? {
+                    args.options.layers.set(layer, false);
+                }
+            }
+            "--frame" => args.options.animation = AnimationTime::Frame(value(&arg, iter.next())?),
+            "--time" => args.options.animation = AnimationTime::Seconds(value(&arg, iter.next())?),
+            "--grid" => args.options.grid = true,
+            "--passability" => args.options.passability = true,
+            "--mapper" => args.options.markers = true,
+            "--geometry" => args.options.geometry = Some(geometry(&arg, iter.next())?),
+            "--header-bounds" => args.options.bounds = Bounds::Header,
+            "--crop" => args.options.bounds = hexes(&arg, iter.next())?,
+            "--pixels" => args.options.bounds = pixels(&arg, iter.next())?,
+            "--scale" => args.options.scale = Some(scale(&arg, iter.next())?),
+            "--size" => args.options.size = Some(size(&arg, iter.next())?),
+            "--batch" => args.batch = Some(value(&arg, iter.next())?),
+            "--sprites" => args.options.sprites = Some(sidecar(&arg, iter.next())?),
+            "--tiles" => args.tiles = Some(value(&arg, iter.next())?),
+            "--padding" => args.options.padding = value(&arg, iter.next())?,
+            "--no-light" => args.options.lighting = false,
+            "--ambient" => args.options.ambient = value(&arg, iter.next())?,
+            "--hour" => args.options.hour = Some(hour(&arg, iter.next())?),
+            "--help" | "-h" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => args.map = Some(arg),
+        }
+    }
+    // Asking for an overlay is asking for its layer, whatever order the options came in.
+    let options = &mut args.options;
+    if options.grid || options.passability || options.markers {
+        options.layers.set(Layer::Overlays, true);
+    }
+    Ok(args)
+}
+
+fn value<T: std::str::FromStr>(option: &str, value: Option<String>) -> ArgResult<T> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("Option {} expects a value", option))
+}
+
+fn geometry(option: &str, value: Option<String>) -> ArgResult<Geometry> {
+    match value.as_deref() {
+        Some("hex") => Ok(Geometry::Hex),
+        Some("square") => Ok(Geometry::Square),
+        _ => Err(format!("Option {} expects hex or square", option)),
+    }
+}
+
+fn sidecar(option: &str, value: Option<String>) -> ArgResult<SpriteSidecar> {
+    match value.as_deref() {
+        Some("json") => Ok(SpriteSidecar::Json),
+        Some("csv") => Ok(SpriteSidecar::Csv),
+        _ => Err(format!("Option {} expects json or csv", option)),
+    }
+}
+
+/// Accepts comma separated layer names.
+fn layers(option: &str, value: Option<String>) -> ArgResult<Vec<Layer>> {
+    let layers: Option<Vec<_>> = value.as_deref().and_then(|value| {
+        value
+            .split(',')
+            .map(|name| Layer::from_name(name.trim()))
+            .collect()
+    });
+    layers.ok_or_else(|| {
+        let names: Vec<_> = Layer::ALL.iter().map(|layer| layer.name()).collect();
+        format!("Option {} expects some of {}", option, names.join(","))
+    })
+}
+
+/// Comma separated numbers.
+fn list<T: std::str::FromStr>(value: &Option<String>) -> Option<Vec<T>> {
+    value
+        .as_deref()
+        .and_then(|value| value.split(',').map(|n| n.trim().parse().ok()).collect())
+}
+
+/// Accepts `X0,Y0,X1,Y1` hex corners.
+fn hexes(option: &str, value: Option<String>) -> ArgResult<Bounds> {
+    match list(&value).as_deref() {
+        Some(&[x0, y0, x1, y1]) => Ok(Bounds::Hexes {
+            from: (x0, y0),
+            to: (x1, y1),
+        }),
+        _ => Err(format!("Option {} expects X0,Y0,X1,Y1", option)),
+    }
+}
+
+/// Accepts `X0,Y0,X1,Y1` map pixel corners.
+fn pixels(option: &str, value: Option<String>) -> ArgResult<Bounds> {
+    match list(&value).as_deref() {
+        Some(&[x0, y0, x1, y1]) => Ok(Bounds::Pixels {
+            from: (x0, y0),
+            to: (x1, y1),
+        }),
+        _ => Err(format!("Option {} expects X0,Y0,X1,Y1", option)),
+    }
+}
+
+/// Accepts a positive zoom factor.
+fn scale(option: &str, value: Option<String>) -> ArgResult<f32> {
+    value
+        .and_then(|value| value.parse().ok())
+        .filter(|scale: &f32| scale.is_finite() && *scale > 0.0)
+        .ok_or_else(|| format!("Option {} expects a positive number", option))
+}
+
+/// Accepts `WIDTHxHEIGHT` of at least one pixel.
+fn size(option: &str, value: Option<String>) -> ArgResult<(u32, u32)> {
+    let size = value.as_deref().and_then(|value| {
+        let mut split = value.splitn(2, 'x');
+        Some((split.next()?.parse().ok()?, split.next()?.parse().ok()?))
+    });
+    size.filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| format!("Option {} expects WIDTHxHEIGHT", option))
+}
+
+/// Accepts `HH:MM` or fractional hours.
+fn hour(option: &str, value: Option<String>) -> ArgResult<f32> {
+    let value = value.ok_or_else(|| format!("Option {} expects a value", option))?;
+    let mut split = value.splitn(2, ':');
+    let parsed = match (split.next(), split.next()) {
+        (Some(hours), Some(minutes)) => hours
+            .parse::<f32>()
+            .and_then(|hours| Ok(hours + minutes.parse::<f32>()? / 60.0)),
+        _ => value.parse(),
+    };
+    parsed.map_err(|_| format!("Option {} expects HH:MM or hours", option))
+}
+
+/// Maps in a directory, or files matching `*` and `?` wildcards in the last path component.
+fn map_files(batch: &str) -> relievo::Result<Vec<String>> {
+    let path = Path::new(batch);
+    let (dir, pattern) = if path.is_dir() {
+        (path, "*.fomap")
+    } else {
+        let pattern = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        (dir.unwrap_or_else(|| Path::new(".")), pattern)
+    };
+    let entries = std::fs::read_dir(dir).map_err(|err| Error::MapList {
+        path: dir.display().to_string(),
+        error: err.to_string(),
+    })?;
+    let mut maps: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path.file_name().and_then(|name| name.to_str());
+            name.is_some_and(|name| wildcard(pattern.as_bytes(), name.as_bytes()))
+        })
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    maps.sort();
+    Ok(maps)
+}
+
+fn wildcard(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            wildcard(rest, name) || (!name.is_empty() && wildcard(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name))) => wildcard(rest, name),
+        (Some((p, rest)), Some((n, name))) => p == n && wildcard(rest, name),
+        _ => false,
+    }
+}
+
+async fn render(
+    state: &mut State,
+    map: &str,
+    tiles: Option<&str>,
+    options: &RenderOptions,
+) -> relievo::Result<MapDiagnostics> {
+    match tiles {
+        Some(dir) => state.render_pyramid(map, dir, options).await,
+        None => {
+            let output = format!("{}.png", map);
+            state.render_map(map, &output, options).await
+        }
+    }
+}
+
+/// Returns `false` if some maps of a batch failed.
+async fn run(args: Args) -> relievo::Result<bool> {
+    let mut state = State::new().await?;
+    let batch = match &args.batch {
+        Some(batch) => batch,
+        None => {
+            let map = args.map.unwrap_or_else(|| state.config.open_map.clone());
+            let diagnostics =
+                render(&mut state, &map, args.tiles.as_deref(), &args.options).await?;
+            print!("{}", diagnostics);
+            return Ok(true);
+        }
+    };
+
+    let maps = map_files(batch)?;
+    let mut failed = vec![];
+    for map in &maps {
+        println!("Map {}", map);
+        // Every map gets its own pyramid directory.
+        let tiles = args.tiles.as_ref().map(|dir| {
+            let name = Path::new(map).file_stem().unwrap_or_default();
+            Path::new(dir).join(name).to_string_lossy().into_owned()
+        });
+        match render(&mut state, map, tiles.as_deref(), &args.options).await {
+            Ok(diagnostics) => print!("{}", diagnostics),
+            Err(err) => {
+                println!("Error: {}", err);
+                failed.push((map, err));
+            }
+        }
+    }
+
+    println!(
+        "Rendered {} of {} maps.",
+        maps.len() - failed.len(),
+        maps.len()
+    );
+    for (map, err) in &failed {
+        println!("  {}: {}", map, err);
+    }
+    Ok(failed.is_empty())
 }
 
 fn main() {
@@ -14,7 +298,26 @@ fn main() {
     {
         //subscriber::initialize_default_subscriber(None);
         tracing_subscriber::fmt::init();
-        futures::executor::block_on(run());
+        let args = match parse_args() {
+            Ok(args) => args,
+            // `--help` comes back as an empty error.
+            Err(err) if err.is_empty() => {
+                print!("{}", USAGE);
+                return;
+            }
+            Err(err) => {
+                eprint!("Error: {}\n\n{}", err, USAGE);
+                std::process::exit(2);
+            }
+        };
+        match futures::executor::block_on(run(args)) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
     }
     #[cfg(target_arch = "wasm32")]
     {
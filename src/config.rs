@@ -1,26 +1,35 @@
+use crate::{Error, Geometry, Result};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Config {
     pub open_map: String,
+    /// Grid layout of the maps, unless set per map in `RenderOptions`.
+    #[serde(default)]
+    pub geometry: Geometry,
     pub window: Window,
     pub paths: Paths,
+    #[serde(default)]
+    pub grid: Grid,
+    #[serde(default)]
+    pub render: Render,
 }
 
 impl Config {
-    pub fn load() -> Self {
+    pub fn load() -> Result<Self> {
         let path = std::path::Path::new("config.toml");
-        let config;
+        let config_error = |err: &dyn std::fmt::Display| Error::Config(err.to_string());
         if path.exists() {
-            let string = std::fs::read_to_string(path).expect("Read config.toml");
-            config = toml::from_str(&string).expect("Parse config.toml");
+            let string = std::fs::read_to_string(path).map_err(|err| config_error(&err))?;
+            toml::from_str(&string).map_err(|err| config_error(&err))
         } else {
-            config = Default::default();
-            let string = toml::to_string(&config).expect("Encode default config");
-            std::fs::write(path, &string).expect("Write default convig.toml");
-            panic!("Edit config.toml!");
+            let config = Self::default();
+            let string = toml::to_string(&config).map_err(|err| config_error(&err))?;
+            std::fs::write(path, &string).map_err(|err| config_error(&err))?;
+            Err(Error::Config(
+                "default config.toml was written, edit it!".into(),
+            ))
         }
-        config
     }
 }
 
@@ -36,7 +45,49 @@ pub struct Window {
 pub struct Paths {
     //pub maps: String,
     pub client: String,
+    /// Proto lists: a `.lst` file, or a directory to load every `.lst` file from.
     pub items_lst: String,
+    #[serde(default)]
+    pub scenery_lst: String,
+    #[serde(default)]
+    pub critters_lst: String,
+    #[serde(default)]
+    pub critter_types: String,
     pub pallette: String,
     pub shaders: String,
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Grid {
+    pub color: [f32; 3],
+    pub opacity: f32,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 1.0, 1.0],
+            opacity: 0.25,
+        }
+    }
+}
+
+impl Grid {
+    pub fn rgba(&self) -> [u8; 4] {
+        let norm = |value: f32| (value.max(0.0).min(1.0) * 255.0) as u8;
+        let [r, g, b] = self.color;
+        [norm(r), norm(g), norm(b), norm(self.opacity)]
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Render {
+    /// Largest texture rendered at once, bigger outputs are rendered in tiles.
+    pub tile_size: u32,
+}
+
+impl Default for Render {
+    fn default() -> Self {
+        Self { tile_size: 4096 }
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+/// Everything that can stop a map from being rendered.
+#[derive(Debug)]
+pub enum Error {
+    /// `config.toml` is missing or can't be parsed.
+    Config(String),
+    /// Game data archives or other data files can't be opened.
+    DataInit(String),
+    /// Proto list or proto file can't be read.
+    MissingProto { path: String, error: String },
+    /// Map file can't be read or parsed.
+    MapParse { path: String, error: String },
+    /// Directory of a batch of maps can't be read.
+    MapList { path: String, error: String },
+    /// Output file can't be written.
+    Output { path: String, error: String },
+    /// Map has no sprites to frame with `Bounds::Sprites`.
+    NothingToDraw,
+    /// Tile hash that has no path in the map's tile table.
+    UnknownTileHash {
+        hash: String,
+        hex_x: u16,
+        hex_y: u16,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn map_parse(path: &str, error: impl fmt::Debug) -> Self {
+        Error::MapParse {
+            path: path.to_owned(),
+            error: format!("{:?}", error),
+        }
+    }
+    pub(crate) fn output(path: impl fmt::Display, error: impl fmt::Debug) -> Self {
+        Error::Output {
+            path: path.to_string(),
+            error: format!("{:?}", error),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(error) => write!(f, "config error: {}", error),
+            Error::DataInit(error) => write!(f, "can't load game data: {}", error),
+            Error::MissingProto { path, error } => {
+                write!(f, "can't read protos from {:?}: {}", path, error)
+            }
+            Error::MapParse { path, error } => write!(f, "can't parse map {:?}: {}", path, error),
+            Error::MapList { path, error } => {
+                write!(f, "can't list maps in {:?}: {}", path, error)
+            }
+            Error::Output { path, error } => write!(f, "can't write {:?}: {}", path, error),
+            Error::NothingToDraw => write!(f, "map has nothing to draw"),
+            Error::UnknownTileHash { hash, hex_x, hex_y } => {
+                write!(f, "unknown tile hash {} at hex {}:{}", hash, hex_x, hex_y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,289 @@
+use crate::{ImageOffset, ImageSize, IntoComponents};
+
+/// Fallout FRM animation with up to six directions.
+#[derive(Debug)]
+pub struct Frm {
+    pub fps: u16,
+    pub directions: Vec<FrmDirection>,
+}
+
+#[derive(Debug)]
+pub struct FrmDirection {
+    pub shift_x: i16,
+    pub shift_y: i16,
+    pub frames: Vec<FrmFrame>,
+}
+
+#[derive(Debug)]
+pub struct FrmFrame {
+    pub width: u16,
+    pub height: u16,
+    pub offset_x: i16,
+    pub offset_y: i16,
+    /// Palette indices, row by row. Index 0 is transparent.
+    pub pixels: Vec<u8>,
+}
+
+const HEADER_LEN: usize = 62;
+const DIRECTIONS: usize = 6;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn at(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + len;
+        let slice = self
+            .data
+            .get(self.pos..end)
+            .ok_or_else(|| format!("Unexpected end of FRM at {}", self.pos))?;
+        self.pos = end;
+        Ok(slice)
+    }
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(self.u16()? as i16)
+    }
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+impl Frm {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let mut header = Reader::at(data, 0);
+        let _version = header.u32()?;
+        let fps = header.u16()?;
+        let _action_frame = header.u16()?;
+        let frames_per_direction = header.u16()?;
+        let mut shift_x = [0i16; DIRECTIONS];
+        for shift in shift_x.iter_mut() {
+            *shift = header.i16()?;
+        }
+        let mut shift_y = [0i16; DIRECTIONS];
+        for shift in shift_y.iter_mut() {
+            *shift = header.i16()?;
+        }
+        let mut offsets = [0u32; DIRECTIONS];
+        for offset in offsets.iter_mut() {
+            *offset = header.u32()?;
+        }
+
+        let mut directions = Vec::with_capacity(DIRECTIONS);
+        for dir in 0..DIRECTIONS {
+            // Single-direction FRMs point every direction to the same frames.
+            if dir > 0 && offsets[dir] == 0 {
+                break;
+            }
+            let mut reader = Reader::at(data, HEADER_LEN + offsets[dir] as usize);
+            let mut frames = Vec::with_capacity(frames_per_direction as usize);
+            for _ in 0..frames_per_direction {
+                let width = reader.u16()?;
+                let height = reader.u16()?;
+                let _size = reader.u32()?;
+                let offset_x = reader.i16()?;
+                let offset_y = reader.i16()?;
+                let pixels = reader.take(width as usize * height as usize)?.to_vec();
+                frames.push(FrmFrame {
+                    width,
+                    height,
+                    offset_x,
+                    offset_y,
+                    pixels,
+                });
+            }
+            directions.push(FrmDirection {
+                shift_x: shift_x[dir],
+                shift_y: shift_y[dir],
+                frames,
+            });
+        }
+        if directions.is_empty() {
+            return Err("FRM has no directions".into());
+        }
+        Ok(Self { fps, directions })
+    }
+    /// Direction with fallback to the first one for single-direction animations.
+    pub fn direction(&self, dir: u8) -> &FrmDirection {
+        self.directions
+            .get(dir as usize)
+            .unwrap_or(&self.directions[0])
+    }
+}
+
+/// Fallout palette, 256 colors with 6-bit components.
+pub struct Palette([[u8; 3]; 256]);
+
+impl Palette {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.len() < 256 * 3 {
+            return Err(format!("Palette is too short: {} bytes", data.len()));
+        }
+        let mut colors = [[0u8; 3]; 256];
+        for (color, rgb) in colors.iter_mut().zip(data.chunks(3)) {
+            for (component, value) in color.iter_mut().zip(rgb) {
+                *component = (*value).min(63) * 4;
+            }
+        }
+        Ok(Palette(colors))
+    }
+    pub fn to_rgba(&self, frame: &FrmFrame) -> image::RgbaImage {
+        let mut image = image::RgbaImage::new(frame.width as u32, frame.height as u32);
+        for (pixel, &index) in image.pixels_mut().zip(&frame.pixels) {
+            if index != 0 {
+                let [r, g, b] = self.0[index as usize];
+                *pixel = image::Rgba([r, g, b, 255]);
+            }
+        }
+        image
+    }
+}
+
+/// Single decoded frame, positioned like the engine does: bottom center at the hex.
+pub struct FrameImage {
+    pub image: image::RgbaImage,
+    pub offset_x: i16,
+    pub offset_y: i16,
+}
+
+impl FrameImage {
+    pub fn first_frame(frm: &Frm, dir: u8, palette: &Palette) -> Result<Self, String> {
+        Self::frames(frm, dir, palette)
+            .into_iter()
+            .next()
+            .ok_or_else(|| "FRM direction has no frames".to_string())
+    }
+    /// Every frame of the direction; frame offsets accumulate as the animation plays.
+    pub fn frames(frm: &Frm, dir: u8, palette: &Palette) -> Vec<Self> {
+        let direction = frm.direction(dir);
+        let (mut next_x, mut next_y) = (direction.shift_x, direction.shift_y);
+        direction
+            .frames
+            .iter()
+            .map(|frame| {
+                next_x += frame.offset_x;
+                next_y += frame.offset_y;
+                Self {
+                    image: palette.to_rgba(frame),
+                    offset_x: next_x - (frame.width / 2) as i16,
+                    offset_y: next_y - frame.height as i16,
+                }
+            })
+            .collect()
+    }
+}
+
+impl IntoComponents for FrameImage {
+    type Components = (ImageSize, ImageOffset, image::RgbaImage);
+    fn into_components(self) -> Self::Components {
+        (
+            ImageSize(euclid::size2(
+                self.image.width() as u16,
+                self.image.height() as u16,
+            )),
+            ImageOffset {
+                x: self.offset_x,
+                y: self.offset_y,
+            },
+            self.image,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u16, height: u16, offset_x: i16, offset_y: i16, fill: u8) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&(width as u32 * height as u32).to_be_bytes());
+        data.extend_from_slice(&offset_x.to_be_bytes());
+        data.extend_from_slice(&offset_y.to_be_bytes());
+        data.extend(vec![fill; width as usize * height as usize]);
+        data
+    }
+
+    fn frm(frames_per_direction: u16, shift_x: i16, offsets: [u32; 6], body: &[u8]) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&4u32.to_be_bytes());
+        data.extend_from_slice(&12u16.to_be_bytes());
+        data.extend_from_slice(&0u16.to_be_bytes());
+        data.extend_from_slice(&frames_per_direction.to_be_bytes());
+        for dir in 0..DIRECTIONS as i16 {
+            data.extend_from_slice(&(shift_x + dir).to_be_bytes());
+        }
+        for _ in 0..DIRECTIONS {
+            data.extend_from_slice(&(-1i16).to_be_bytes());
+        }
+        for offset in offsets.iter() {
+            data.extend_from_slice(&offset.to_be_bytes());
+        }
+        data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        assert_eq!(data.len(), HEADER_LEN);
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn frames_start_after_header() {
+        let body = [frame(2, 3, 1, -1, 7), frame(1, 1, 0, 2, 9)].concat();
+        let frm = Frm::parse(&frm(2, 5, [0; 6], &body)).unwrap();
+        assert_eq!(frm.fps, 12);
+        assert_eq!(frm.directions.len(), 1);
+        let frames = &frm.directions[0].frames;
+        assert_eq!((frames[0].width, frames[0].height), (2, 3));
+        assert_eq!((frames[0].offset_x, frames[0].offset_y), (1, -1));
+        assert_eq!(frames[0].pixels, vec![7; 6]);
+        assert_eq!((frames[1].offset_x, frames[1].offset_y), (0, 2));
+        assert_eq!(frames[1].pixels, vec![9]);
+    }
+
+    #[test]
+    fn directions_follow_offsets() {
+        // Directions after the first one are stored in reverse to make sure offsets are followed.
+        let order = [0, 5, 4, 3, 2, 1];
+        let frames: Vec<_> = order.iter().map(|&dir| frame(1, 1, 0, 0, dir)).collect();
+        let len = frames[0].len() as u32;
+        let mut offsets = [0; 6];
+        for (position, &dir) in order.iter().enumerate() {
+            offsets[dir as usize] = position as u32 * len;
+        }
+        let frm = Frm::parse(&frm(1, 10, offsets, &frames.concat())).unwrap();
+        assert_eq!(frm.directions.len(), DIRECTIONS);
+        for dir in 0..DIRECTIONS {
+            let direction = frm.direction(dir as u8);
+            assert_eq!(direction.shift_x, 10 + dir as i16);
+            assert_eq!(direction.shift_y, -1);
+            assert_eq!(direction.frames[0].pixels, vec![dir as u8]);
+        }
+    }
+
+    #[test]
+    fn single_direction_falls_back_to_first() {
+        let frm = Frm::parse(&frm(1, 0, [0; 6], &frame(1, 1, 0, 0, 3))).unwrap();
+        assert_eq!(frm.directions.len(), 1);
+        for dir in 0..DIRECTIONS as u8 {
+            assert_eq!(frm.direction(dir).frames[0].pixels, vec![3]);
+        }
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let data = frm(1, 0, [0; 6], &frame(2, 2, 0, 0, 1));
+        assert!(Frm::parse(&data[..data.len() - 1]).is_err());
+        assert!(Frm::parse(&data[..HEADER_LEN - 10]).is_err());
+        // Second frame promised by the header is missing.
+        assert!(Frm::parse(&frm(2, 0, [0; 6], &frame(1, 1, 0, 0, 1))).is_err());
+    }
+}
@@ -0,0 +1,409 @@
+use serde::{Deserialize, Serialize};
+
+/// Map grid layout, FOnline maps are either hexagonal or isometric square.
+/// Follows the engine's `GetHexInterval` with the default 32px cell width and 12px line height.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Geometry {
+    #[default]
+    Hex,
+    Square,
+}
+
+/// Hex corners relative to the hex center, clockwise from the top.
+const HEX_CORNERS: [(i32, i32); 6] = [(0, -8), (16, -4), (16, 4), (0, 8), (-16, 4), (-16, -4)];
+/// Square cell corners relative to the cell center, clockwise from the top.
+const SQUARE_CORNERS: [(i32, i32); 4] = [(0, -12), (16, 0), (0, 12), (-16, 0)];
+
+/// Tile sprites are anchored this far from the center of their hex.
+const TILE_OFFSET: (i32, i32) = (-24, 24);
+
+/// Engine limit of the map size in hexes.
+const MAX_HEX: i32 = 1000;
+/// Number of distinct hex positions in square draw order.
+const SQUARE_POSITIONS: i32 = (2 * MAX_HEX + 1) * MAX_HEX;
+/// First draw order class that is not flat, flat sprites go below every other one.
+const DRAW_ORDER: u32 = 20;
+const DRAW_ORDER_LAST: i32 = 40;
+
+impl Geometry {
+    /// Pixel position of the hex center, objects are anchored there.
+    pub fn position(self, hex_x: u16, hex_y: u16) -> (i32, i32) {
+        let (x, y) = (hex_x as i32, hex_y as i32);
+        match self {
+            Geometry::Hex => (
+                /*x = */ y * 16 - x * 24 - (x % 2) * 8,
+                /*y = */ y * 12 + x * 6 - (x % 2) * 6,
+            ),
+            Geometry::Square => (/*x = */ (y - x) * 16, /*y = */ (y + x) * 12),
+        }
+    }
+
+    /// Pixel position of the tile top left corner, tiles cover two hexes in each direction.
+    pub fn tile_position(self, hex_x: u16, hex_y: u16) -> (i32, i32) {
+        match self {
+            Geometry::Hex => {
+                // Tiles sit on even columns, the odd column correction is not applied.
+                let (x, y) = (hex_x as i32, hex_y as i32);
+                (
+                    /*x = */ y * 16 - x * 24 + TILE_OFFSET.0,
+                    /*y = */ y * 12 + x * 6 + TILE_OFFSET.1,
+                )
+            }
+            Geometry::Square => {
+                let (x, y) = self.position(hex_x, hex_y);
+                (x + TILE_OFFSET.0, y + TILE_OFFSET.1)
+            }
+        }
+    }
+
+    /// Cell corners relative to the cell center, clockwise from the top.
+    pub fn corners(self) -> &'static [(i32, i32)] {
+        match self {
+            Geometry::Hex => &HEX_CORNERS,
+            Geometry::Square => &SQUARE_CORNERS,
+        }
+    }
+
+    /// Sort key of a sprite of the draw order class on the hex, lower keys are drawn first.
+    pub fn draw_order(self, order: u32, hex_x: u16, hex_y: u16) -> i32 {
+        match self {
+            Geometry::Hex => {
+                draw_geometry::fo::draw_order_pos_int(order, primitives::Hex::new(hex_x, hex_y))
+                    .unwrap_or(0)
+            }
+            Geometry::Square => {
+                // Rows of equal `x + y` share the screen line, back rows first.
+                let row = hex_x as i32 + hex_y as i32;
+                let pos = row * MAX_HEX + hex_y as i32;
+                if order < DRAW_ORDER {
+                    order as i32 * SQUARE_POSITIONS + pos
+                } else {
+                    DRAW_ORDER as i32 * SQUARE_POSITIONS + pos * DRAW_ORDER_LAST + order as i32
+                }
+            }
+        }
+    }
+
+    /// Hex whose outline contains the map pixel.
+    pub fn pixel_hex(self, x: f32, y: f32) -> Option<(u16, u16)> {
+        let (approx_x, approx_y) = match self {
+            // Solve `position` without the odd column correction, then check the neighborhood.
+            Geometry::Hex => ((4.0 * y - 3.0 * x) / 96.0, (x + 4.0 * y) / 64.0),
+            Geometry::Square => ((y / 12.0 - x / 16.0) / 2.0, (y / 12.0 + x / 16.0) / 2.0),
+        };
+        let (approx_x, approx_y) = (approx_x.round() as i32, approx_y.round() as i32);
+        for hex_y in approx_y - 2..=approx_y + 2 {
+            for hex_x in approx_x - 2..=approx_x + 2 {
+                if hex_x < 0 || hex_y < 0 || hex_x > u16::MAX as i32 || hex_y > u16::MAX as i32 {
+                    continue;
+                }
+                let (hex_x, hex_y) = (hex_x as u16, hex_y as u16);
+                let (cx, cy) = self.position(hex_x, hex_y);
+                if self.contains(x - cx as f32, y - cy as f32) {
+                    return Some((hex_x, hex_y));
+                }
+            }
+        }
+        None
+    }
+
+    /// Number of directions to step from a cell: 6 for hexes, 8 for squares.
+    pub fn dirs(self) -> u8 {
+        match self {
+            Geometry::Hex => 6,
+            Geometry::Square => 8,
+        }
+    }
+
+    /// Adjacent cell in the direction, as the engine's `MoveHexByDir`.
+    /// `None` when it would leave the `u16` coordinate range or `dir` is not below `dirs()`.
+    pub fn move_by_dir(self, hex_x: u16, hex_y: u16, dir: u8) -> Option<(u16, u16)> {
+        let (x, y) = (hex_x as i32, hex_y as i32);
+        let (x, y) = match (self, dir) {
+            (Geometry::Hex, 0) => (x - 1, if x % 2 == 1 { y - 1 } else { y }),
+            (Geometry::Hex, 1) => (x - 1, if x % 2 == 0 { y + 1 } else { y }),
+            (Geometry::Hex, 2) => (x, y + 1),
+            (Geometry::Hex, 3) => (x + 1, if x % 2 == 0 { y + 1 } else { y }),
+            (Geometry::Hex, 4) => (x + 1, if x % 2 == 1 { y - 1 } else { y }),
+            (Geometry::Hex, 5) => (x, y - 1),
+            (Geometry::Square, 0) => (x - 1, y),
+            (Geometry::Square, 1) => (x - 1, y + 1),
+            (Geometry::Square, 2) => (x, y + 1),
+            (Geometry::Square, 3) => (x + 1, y + 1),
+            (Geometry::Square, 4) => (x + 1, y),
+            (Geometry::Square, 5) => (x + 1, y - 1),
+            (Geometry::Square, 6) => (x, y - 1),
+            (Geometry::Square, 7) => (x - 1, y - 1),
+            _ => return None,
+        };
+        let range = 0..=u16::MAX as i32;
+        if range.contains(&x) && range.contains(&y) {
+            Some((x as u16, y as u16))
+        } else {
+            None
+        }
+    }
+
+    /// Adjacent cells in direction order, cells outside of the coordinate range are skipped.
+    pub fn neighbors(self, hex_x: u16, hex_y: u16) -> Vec<(u16, u16)> {
+        (0..self.dirs())
+            .filter_map(|dir| self.move_by_dir(hex_x, hex_y, dir))
+            .collect()
+    }
+
+    /// Number of steps between two cells, same as `DistGame` of the engine.
+    pub fn distance(self, from: (u16, u16), to: (u16, u16)) -> u32 {
+        let (x1, y1) = (from.0 as i32, from.1 as i32);
+        let (x2, y2) = (to.0 as i32, to.1 as i32);
+        let dx = (x1 - x2).abs();
+        match self {
+            Geometry::Hex => {
+                let rx = if x1 % 2 == 0 {
+                    if y2 <= y1 {
+                        y1 - y2 - dx / 2
+                    } else {
+                        y2 - y1 - (dx + 1) / 2
+                    }
+                } else if y2 >= y1 {
+                    y2 - y1 - dx / 2
+                } else {
+                    y1 - y2 - (dx + 1) / 2
+                };
+                (dx + rx.max(0)) as u32
+            }
+            Geometry::Square => dx.max((y1 - y2).abs()) as u32,
+        }
+    }
+
+    /// Cells crossed by the straight line between two cell centers, both ends included.
+    /// Every cell is adjacent to the previous one, the length is `distance + 1`.
+    pub fn line(self, from: (u16, u16), to: (u16, u16)) -> Vec<(u16, u16)> {
+        let steps = self.distance(from, to);
+        let (x0, y0) = self.position(from.0, from.1);
+        let (x1, y1) = self.position(to.0, to.1);
+        // Nudge off cell edges so ties are broken the same way along the line.
+        let (x0, y0) = (x0 as f32 + 1e-2, y0 as f32 + 2e-2);
+        let (x1, y1) = (x1 as f32 + 1e-2, y1 as f32 + 2e-2);
+        let mut cells = Vec::with_capacity(steps as usize + 1);
+        for step in 0..=steps {
+            let t = if steps == 0 {
+                0.0
+            } else {
+                step as f32 / steps as f32
+            };
+            let x = x0 + (x1 - x0) * t;
+            let y = y0 + (y1 - y0) * t;
+            match self.pixel_hex(x, y) {
+                Some(cell) => cells.push(cell),
+                None => break,
+            }
+        }
+        cells
+    }
+
+    /// Point in convex polygon test against the cell corners, the point is relative to the center.
+    fn contains(self, x: f32, y: f32) -> bool {
+        let corners = self.corners();
+        (0..corners.len()).all(|i| {
+            let (x0, y0) = corners[i];
+            let (x1, y1) = corners[(i + 1) % corners.len()];
+            let cross = (x1 - x0) as f32 * (y - y0 as f32) - (y1 - y0) as f32 * (x - x0 as f32);
+            cross >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{hash_map::Entry, HashMap, VecDeque};
+
+    /// Offsets from hex 0:0 as returned by the engine's `GetHexInterval`.
+    const HEX_ENGINE: [((u16, u16), (i32, i32)); 6] = [
+        ((0, 0), (0, 0)),
+        ((1, 0), (-32, 0)),
+        ((2, 0), (-48, 12)),
+        ((0, 1), (16, 12)),
+        ((3, 5), (0, 72)),
+        ((100, 100), (-800, 1800)),
+    ];
+    const SQUARE_ENGINE: [((u16, u16), (i32, i32)); 5] = [
+        ((0, 0), (0, 0)),
+        ((1, 0), (-16, 12)),
+        ((0, 1), (16, 12)),
+        ((3, 5), (32, 96)),
+        ((100, 100), (0, 2400)),
+    ];
+
+    #[test]
+    fn hex_position_matches_engine() {
+        for ((hex_x, hex_y), expected) in HEX_ENGINE.iter() {
+            assert_eq!(Geometry::Hex.position(*hex_x, *hex_y), *expected);
+        }
+    }
+
+    #[test]
+    fn square_position_matches_engine() {
+        for ((hex_x, hex_y), expected) in SQUARE_ENGINE.iter() {
+            assert_eq!(Geometry::Square.position(*hex_x, *hex_y), *expected);
+        }
+    }
+
+    #[test]
+    fn tiles_are_offset_from_even_hexes() {
+        for geometry in [Geometry::Hex, Geometry::Square].iter() {
+            for &(hex_x, hex_y) in [(0, 0), (2, 4), (10, 6), (98, 100)].iter() {
+                let (x, y) = geometry.position(hex_x, hex_y);
+                assert_eq!(
+                    geometry.tile_position(hex_x, hex_y),
+                    (x + TILE_OFFSET.0, y + TILE_OFFSET.1)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_hex_finds_cell_centers() {
+        for geometry in [Geometry::Hex, Geometry::Square].iter() {
+            for hex_y in 0..40 {
+                for hex_x in 0..40 {
+                    let (x, y) = geometry.position(hex_x, hex_y);
+                    assert_eq!(
+                        geometry.pixel_hex(x as f32, y as f32),
+                        Some((hex_x, hex_y)),
+                        "{:?} {}:{}",
+                        geometry,
+                        hex_x,
+                        hex_y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn square_draw_order_goes_back_to_front() {
+        let geometry = Geometry::Square;
+        let item = DRAW_ORDER + 4;
+        // Further down the screen is drawn later.
+        assert!(geometry.draw_order(item, 5, 5) < geometry.draw_order(item, 5, 6));
+        assert!(geometry.draw_order(item, 5, 5) < geometry.draw_order(item, 6, 5));
+        // Flat sprites go below everything else.
+        assert!(geometry.draw_order(0, 90, 90) < geometry.draw_order(item, 0, 0));
+        // Classes on the same hex keep their order.
+        assert!(geometry.draw_order(item, 5, 5) < geometry.draw_order(item + 1, 5, 5));
+    }
+
+    const GEOMETRIES: [Geometry; 2] = [Geometry::Hex, Geometry::Square];
+
+    #[test]
+    fn pixel_hex_round_trips_inside_cells() {
+        for geometry in GEOMETRIES.iter() {
+            for hex_y in 0..40 {
+                for hex_x in 0..40 {
+                    let (x, y) = geometry.position(hex_x, hex_y);
+                    for (dx, dy) in
+                        [(-7.5, 0.0), (7.5, 0.0), (0.0, -3.5), (0.0, 3.5), (3.0, 2.0)].iter()
+                    {
+                        assert_eq!(
+                            geometry.pixel_hex(x as f32 + dx, y as f32 + dy),
+                            Some((hex_x, hex_y)),
+                            "{:?} {}:{} +{}:{}",
+                            geometry,
+                            hex_x,
+                            hex_y,
+                            dx,
+                            dy
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neighbors_are_one_step_away_and_symmetric() {
+        for geometry in GEOMETRIES.iter() {
+            for hex_y in 1..40 {
+                for hex_x in 1..40 {
+                    let neighbors = geometry.neighbors(hex_x, hex_y);
+                    assert_eq!(neighbors.len(), geometry.dirs() as usize);
+                    for &neighbor in &neighbors {
+                        assert_eq!(geometry.distance((hex_x, hex_y), neighbor), 1);
+                        assert!(geometry
+                            .neighbors(neighbor.0, neighbor.1)
+                            .contains(&(hex_x, hex_y)));
+                    }
+                }
+            }
+        }
+        assert_eq!(Geometry::Hex.neighbors(0, 0).len(), 3);
+        assert_eq!(Geometry::Hex.move_by_dir(0, 0, 6), None);
+    }
+
+    #[test]
+    fn distance_matches_breadth_first_search() {
+        for geometry in GEOMETRIES.iter() {
+            let from = (30, 30);
+            let mut steps = HashMap::new();
+            steps.insert(from, 0);
+            let mut queue = VecDeque::from(vec![from]);
+            while let Some(cell) = queue.pop_front() {
+                let step = steps[&cell];
+                if step == 15 {
+                    continue;
+                }
+                for neighbor in geometry.neighbors(cell.0, cell.1) {
+                    if let Entry::Vacant(entry) = steps.entry(neighbor) {
+                        entry.insert(step + 1);
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+            for (cell, step) in steps {
+                assert_eq!(
+                    geometry.distance(from, cell),
+                    step,
+                    "{:?} {:?}",
+                    geometry,
+                    cell
+                );
+                assert_eq!(
+                    geometry.distance(cell, from),
+                    step,
+                    "{:?} {:?}",
+                    geometry,
+                    cell
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lines_are_connected() {
+        for geometry in GEOMETRIES.iter() {
+            for &from in [(20, 20), (21, 20), (0, 0), (33, 7)].iter() {
+                for hex_y in 0..40 {
+                    for hex_x in 0..40 {
+                        let to = (hex_x, hex_y);
+                        let line = geometry.line(from, to);
+                        assert_eq!(line.len() as u32, geometry.distance(from, to) + 1);
+                        assert_eq!(line.first(), Some(&from));
+                        assert_eq!(line.last(), Some(&to));
+                        for pair in line.windows(2) {
+                            assert_eq!(
+                                geometry.distance(pair[0], pair[1]),
+                                1,
+                                "{:?} {:?} {:?}",
+                                geometry,
+                                from,
+                                to
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,27 @@
 mod assets;
 mod config;
+mod error;
+mod frm;
+pub mod geometry;
 mod library;
+mod light;
+mod markers;
+mod overlay;
+mod passability;
 mod sprite_map;
 mod wg;
 
-use assets::{AssetKey, Assets, IntoComponents, Load, SelfInserter};
+pub use assets::FailedAsset;
+use assets::{AnimationViews, AssetKey, Assets, IntoComponents, Load, SelfInserter};
 use config::Config;
-use library::{Image, ImageOffset, ImageSize, Library};
+pub use error::{Error, Result};
+pub use geometry::Geometry;
+use library::{Animation, CritterImage, Image, ImageOffset, ImageSize};
+pub use library::{CritterProto, Library, Proto, ProtoKind};
+pub use sprite_map::{
+    AnimationTime, Bounds, Layer, Layers, MapDiagnostics, ObjectInfo, OutputView, PyramidManifest,
+    RenderOptions, SpritePlacement, SpriteSidecar, UnknownProto, PYRAMID_TILE_SIZE,
+};
 use sprite_map::{SpriteMap, SpriteMapRenderer};
 use wg::{MaterialId, SizedBuffer, SizedTexture, SpriteUniforms, TextureView, Wgpu, WgpuUpload};
 
@@ -19,13 +34,14 @@ pub struct State {
     assets: Assets,
     wgpu: Wgpu,
     pub config: Config,
+    failed: Vec<FailedAsset>,
 }
 impl State {
-    pub async fn new() -> Self {
-        let config = Config::load();
+    pub async fn new() -> Result<Self> {
+        let config = Config::load()?;
 
         println!("Loading library...");
-        let library = Library::load(&config.paths);
+        let library = Library::load(&config.paths)?;
         let assets = Assets::new();
 
         println!("Initializing GPU...");
@@ -33,46 +49,151 @@ impl State {
 
         println!("Ready to work!");
 
-        Self {
+        Ok(Self {
             library,
             assets,
             wgpu,
             config,
-        }
+            failed: vec![],
+        })
     }
-    fn prepare_map(&mut self, map: &str, format: wgpu::TextureFormat) -> SpriteMapRenderer {
+    fn prepare_map(
+        &mut self,
+        map: &str,
+        format: wgpu::TextureFormat,
+        options: &RenderOptions,
+    ) -> Result<(SpriteMapRenderer, MapDiagnostics)> {
         println!("Loading map...");
-        let mut map = SpriteMap::open(map, &self.library, &mut self.assets);
+        let geometry = options.geometry.unwrap_or(self.config.geometry);
+        let (mut map, diagnostics) =
+            SpriteMap::open(map, geometry, &self.library, &mut self.assets)?;
 
         println!("Sorting map sprites...");
         map.sort_sprites();
 
         println!("Loading assets...");
         self.assets.load(&self.library);
+        let failed = self.assets.failed(&map.asset_usage());
+        if !failed.is_empty() {
+            println!("{} assets failed to load:", failed.len());
+            for asset in &failed {
+                println!(
+                    "  {} (used {} times): {}",
+                    asset.path, asset.usage, asset.error
+                );
+            }
+        }
+        self.failed = failed;
 
         println!("Uploading textures to gpu...");
         //self.assets.wgpu_upload::<image::RgbaImage>(&mut self.wgpu);
         self.assets.sized_upload(&mut self.wgpu);
 
         println!("Prepare pipeline...");
-        let renderer = map.into_renderer(&self.wgpu, &self.assets, format, &self.config);
+        let mut renderer = map.into_renderer(&self.wgpu, &self.assets, format, &self.config);
+        renderer.set_layers(options.layers);
+        renderer.set_lighting(options.lighting, options.ambient);
+        renderer.set_hour(options.hour);
+        renderer.set_grid(&self.wgpu, options.grid);
+        renderer.set_passability(options.passability);
+        renderer.set_markers(options.markers);
+        renderer.set_bounds(options.bounds, options.padding)?;
+        renderer.set_output(options.scale, options.size);
+        renderer.set_animation_time(&self.wgpu, options.animation);
 
-        renderer
+        Ok((renderer, diagnostics))
     }
-    pub async fn render_map(&mut self, map: &str, output: &str) {
-        let renderer = self.prepare_map(map, wgpu::TextureFormat::Rgba8UnormSrgb);
+    pub async fn render_map(
+        &mut self,
+        map: &str,
+        output: &str,
+        options: &RenderOptions,
+    ) -> Result<MapDiagnostics> {
+        let (renderer, diagnostics) =
+            self.prepare_map(map, wgpu::TextureFormat::Rgba8UnormSrgb, options)?;
 
-        println!("Rendering...");
-        let sized_buffer = renderer.render_into_texture(&self.wgpu);
+        println!("Rendering to png...");
+        renderer
+            .render_to_png(&self.wgpu, output, self.config.render.tile_size)
+            .await?;
+        if let Some(sidecar) = options.sprites {
+            let path = std::path::Path::new(output).with_extension(sidecar.extension());
+            println!("Writing sprite list to {}...", path.display());
+            sidecar.write(&path, &renderer.placements(&self.assets))?;
+        }
 
-        println!("Saving to png...");
-        sized_buffer.save_to_png(&self.wgpu.device, output).await;
+        println!("Done!");
+        Ok(diagnostics)
+    }
+    /// Writes a `z/x/y.png` tile pyramid of the map and its `manifest.json` into `dir`.
+    pub async fn render_pyramid(
+        &mut self,
+        map: &str,
+        dir: &str,
+        options: &RenderOptions,
+    ) -> Result<MapDiagnostics> {
+        let (renderer, diagnostics) =
+            self.prepare_map(map, wgpu::TextureFormat::Rgba8UnormSrgb, options)?;
+
+        println!("Rendering tiles...");
+        let dir = std::path::Path::new(dir);
+        let manifest = renderer.render_pyramid(&self.wgpu, dir).await?;
+        let manifest_path = dir.join("manifest.json");
+        let json = serde_json::to_string_pretty(&manifest)
+            .map_err(|err| Error::output(manifest_path.display(), err))?;
+        std::fs::write(&manifest_path, json)
+            .map_err(|err| Error::output(manifest_path.display(), err))?;
 
         println!("Done!");
+        Ok(diagnostics)
+    }
+    /// Loaded protos and game data, shared with other tools.
+    pub fn library(&self) -> &Library {
+        &self.library
+    }
+    /// Assets of the last prepared map that failed to load, they are drawn as a magenta checker.
+    pub fn failed_assets(&self) -> &[FailedAsset] {
+        &self.failed
+    }
+    /// Prints the object behind a sprite picked in the viewer.
+    fn inspect(&self, renderer: &SpriteMapRenderer, object: &ObjectInfo) {
+        println!("Object #{}: {:#?}", object.index, object);
+        if object.kind == ProtoKind::Critter {
+            match self.library.critter_proto(object.proto_id) {
+                Some(proto) => println!("Critter proto: {:#?}", proto),
+                None => println!("Critter proto {} is not loaded", object.proto_id),
+            }
+        } else {
+            match self
+                .library
+                .kind_proto(object.kind, object.proto_id)
+                .and_then(Proto::as_item)
+            {
+                Some(proto) => {
+                    let flags = proto.Flags.unwrap_or(0);
+                    println!(
+                        "Proto flags: {:#x} {:?}",
+                        flags,
+                        library::flag_names(flags as u32)
+                    );
+                    println!("Item proto: {:#?}", proto);
+                }
+                None => println!("Item proto {} is not loaded", object.proto_id),
+            }
+        }
+        for child in renderer.children(object) {
+            println!(
+                "  Child #{}: proto {} uid {:?}",
+                child.index, child.proto_id, child.uid
+            );
+        }
     }
-    pub fn show_map(mut self, map: &str) -> ! {
+    /// Opens the viewer window, returns only if the map can't be prepared.
+    pub fn show_map(mut self, map: &str, options: &RenderOptions) -> Result<()> {
         let format = wgpu::TextureFormat::Bgra8UnormSrgb;
-        let renderer = self.prepare_map(map, format);
+        let (mut renderer, diagnostics) = self.prepare_map(map, format, options)?;
+        print!("{}", diagnostics);
+        let ambient = options.ambient;
         let mut width = self.config.window.width;
         let mut height = self.config.window.height;
 
@@ -140,13 +261,16 @@ impl State {
             }
         }
         let mut keys = Keys::default();
+        let mut cursor = (0.0, 0.0);
+        let mut title = String::new();
+        let mut picked_at = None;
 
         println!("Rendering...");
 
         event_loop.run(move |event, _event_loop, control_flow| {
             use winit::{
                 event::{
-                    ElementState, Event, KeyboardInput, MouseScrollDelta, StartCause,
+                    ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, StartCause,
                     VirtualKeyCode, WindowEvent,
                 },
                 event_loop::ControlFlow,
@@ -198,6 +322,77 @@ impl State {
                             ..
                         } => {
                             keys.input(key, state);
+                            if state == ElementState::Pressed {
+                                match key {
+                                    VirtualKeyCode::R => renderer.toggle_layer(Layer::Roofs),
+                                    // Number keys follow the order of `Layer::ALL`.
+                                    VirtualKeyCode::Key1
+                                    | VirtualKeyCode::Key2
+                                    | VirtualKeyCode::Key3
+                                    | VirtualKeyCode::Key4
+                                    | VirtualKeyCode::Key5
+                                    | VirtualKeyCode::Key6
+                                    | VirtualKeyCode::Key7 => {
+                                        let layer = Layer::ALL
+                                            [key as usize - VirtualKeyCode::Key1 as usize];
+                                        renderer.toggle_layer(layer);
+                                        let shown = renderer.layers().contains(layer);
+                                        println!(
+                                            "Layer {}: {}",
+                                            layer.name(),
+                                            if shown { "shown" } else { "hidden" }
+                                        );
+                                    }
+                                    VirtualKeyCode::G => {
+                                        renderer.set_grid(&self.wgpu, !renderer.grid())
+                                    }
+                                    VirtualKeyCode::M => renderer.set_markers(!renderer.markers()),
+                                    VirtualKeyCode::P => {
+                                        renderer.set_passability(!renderer.passability())
+                                    }
+                                    VirtualKeyCode::L => {
+                                        renderer.set_lighting(!renderer.lighting(), ambient)
+                                    }
+                                    VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+                                        let step = if key == VirtualKeyCode::LBracket {
+                                            -0.5
+                                        } else {
+                                            0.5
+                                        };
+                                        let hour = renderer.hour().unwrap_or(12.0) + step;
+                                        let hour = hour.rem_euclid(24.0);
+                                        println!(
+                                            "Time of day: {:02}:{:02}",
+                                            hour as u32,
+                                            (hour.fract() * 60.0) as u32
+                                        );
+                                        renderer.set_hour(Some(hour));
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        }
+                        WindowEvent::CursorMoved { position, .. } => {
+                            cursor = (position.x as f32, position.y as f32);
+                        }
+                        WindowEvent::MouseInput {
+                            state: ElementState::Released,
+                            button: MouseButton::Left,
+                            ..
+                        } => {
+                            let picked = renderer
+                                .screen_to_map(
+                                    width, height, zoom, shift_x, shift_y, cursor.0, cursor.1,
+                                )
+                                .and_then(|(x, y)| renderer.pick_sprite(&self.assets, x, y));
+                            match picked.as_ref().and_then(|sprite| sprite.object) {
+                                Some(index) => {
+                                    if let Some(object) = renderer.object(index) {
+                                        self.inspect(&renderer, object);
+                                    }
+                                }
+                                None => println!("No object under cursor: {:?}", picked),
+                            }
                         }
                         WindowEvent::CloseRequested => {
                             *control_flow = ControlFlow::Exit;
@@ -210,6 +405,48 @@ impl State {
                     //shift_x = time.cos();
                     shift_x = (shift_x + keys.shift_x() * 0.002 / zoom).min(1.0).max(-1.0);
                     shift_y = (shift_y + keys.shift_y() * 0.002 / zoom).min(1.0).max(-1.0);
+                    // Picking walks every sprite, so only redo it when the view changes.
+                    let pick_key = Some((cursor, zoom, shift_x, shift_y, width, height));
+                    if pick_key != picked_at {
+                        picked_at = pick_key;
+                        let hovered = renderer
+                            .screen_to_map(
+                                width, height, zoom, shift_x, shift_y, cursor.0, cursor.1,
+                            )
+                            .map(|(x, y)| {
+                                (
+                                    renderer.pick_hex(x, y),
+                                    renderer.pick_sprite(&self.assets, x, y),
+                                )
+                            });
+                        let new_title = match hovered {
+                            Some((hex, sprite)) => {
+                                let mut text = String::from("MapViewer");
+                                if let Some((hex_x, hex_y)) = hex {
+                                    text += &format!(" - hex {}, {}", hex_x, hex_y);
+                                }
+                                if let Some(sprite) = sprite {
+                                    text += &format!(
+                                        " - {:?} {} at {}, {}",
+                                        sprite.layer,
+                                        sprite.path.as_deref().unwrap_or("?"),
+                                        sprite.hex_x,
+                                        sprite.hex_y
+                                    );
+                                }
+                                text
+                            }
+                            None => String::from("MapViewer"),
+                        };
+                        if new_title != title {
+                            window.set_title(&new_title);
+                            title = new_title;
+                        }
+                    }
+                    renderer.set_animation_time(
+                        &self.wgpu,
+                        AnimationTime::Seconds(begin.elapsed().as_secs_f32()),
+                    );
                     let frame = swapchain.get_current_frame().unwrap();
                     let view = &frame.output.view;
                     renderer.render_view(&self.wgpu, view, width, height, zoom, shift_x, shift_y);
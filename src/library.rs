@@ -1,6 +1,13 @@
-use crate::{config, IntoComponents, Load, Pixel};
+use crate::{
+    config,
+    frm::{FrameImage, Frm, Palette},
+    Error, IntoComponents, Load, Pixel,
+};
 use fo_data::{Converter, FoData, Retriever};
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 #[cfg(not(feature = "sled-retriever"))]
 type MyRetriever = fo_data::FoData;
@@ -8,24 +15,121 @@ type MyRetriever = fo_data::FoData;
 type MyRetriever = fo_data::SledRetriever;
 
 pub struct Library {
-    items: BTreeMap<u16, fo_proto_format::ProtoItem>,
+    /// Item and scenery protos, both kinds use the item proto format.
+    items: BTreeMap<ProtoKind, BTreeMap<u16, fo_proto_format::ProtoItem>>,
+    critters: BTreeMap<u16, CritterProto>,
+    critter_types: BTreeMap<u32, String>,
+    palette: Option<Palette>,
     retriever: MyRetriever,
 }
 
+/// Proto lists are configured per kind, proto ids are unique within a kind.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProtoKind {
+    Item,
+    Scenery,
+    Critter,
+}
+
+impl ProtoKind {
+    pub const ALL: [ProtoKind; 3] = [ProtoKind::Item, ProtoKind::Scenery, ProtoKind::Critter];
+
+    /// Kind of proto a map object refers to.
+    pub fn of_object(obj: &fo_map_format::Object) -> Self {
+        match obj.kind {
+            fo_map_format::ObjectKind::Critter(_) => ProtoKind::Critter,
+            fo_map_format::ObjectKind::Scenery(_) => ProtoKind::Scenery,
+            _ => ProtoKind::Item,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Proto<'a> {
+    Item(&'a fo_proto_format::ProtoItem),
+    Scenery(&'a fo_proto_format::ProtoItem),
+    Critter(&'a CritterProto),
+}
+
+impl<'a> Proto<'a> {
+    pub fn kind(self) -> ProtoKind {
+        match self {
+            Proto::Item(_) => ProtoKind::Item,
+            Proto::Scenery(_) => ProtoKind::Scenery,
+            Proto::Critter(_) => ProtoKind::Critter,
+        }
+    }
+    /// Item or scenery proto, `None` for critters.
+    pub fn as_item(self) -> Option<&'a fo_proto_format::ProtoItem> {
+        match self {
+            Proto::Item(proto) | Proto::Scenery(proto) => Some(proto),
+            Proto::Critter(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CritterProto {
+    pub pid: u16,
+    pub base_type: u32,
+}
+
+/// Names of the item flags set in `flags`, for display.
+pub fn flag_names(flags: u32) -> Vec<&'static str> {
+    use fo_defines_fo4rp::fos;
+    let known = [
+        (fos::ITEM_HIDDEN, "ITEM_HIDDEN"),
+        (fos::ITEM_FLAT, "ITEM_FLAT"),
+        (fos::ITEM_NO_BLOCK, "ITEM_NO_BLOCK"),
+        (fos::ITEM_SHOOT_THRU, "ITEM_SHOOT_THRU"),
+        (fos::ITEM_LIGHT_THRU, "ITEM_LIGHT_THRU"),
+        (fos::ITEM_LIGHT, "ITEM_LIGHT"),
+        (fos::ITEM_CAN_USE, "ITEM_CAN_USE"),
+        (fos::ITEM_CAN_PICKUP, "ITEM_CAN_PICKUP"),
+    ];
+    known
+        .iter()
+        .filter(|(flag, _)| flags & *flag as u32 != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// Critter conditions, as stored in `Critter_Cond` of map objects.
+pub const COND_LIFE: u8 = 1;
+pub const COND_DEAD: u8 = 3;
+
 impl Library {
-    pub fn load(paths: &config::Paths) -> Self {
-        let items = fo_proto_format::build_btree(&paths.items_lst);
+    pub fn load(paths: &config::Paths) -> Result<Self, Error> {
+        let mut items = BTreeMap::new();
+        for (kind, path) in &[
+            (ProtoKind::Item, &paths.items_lst),
+            (ProtoKind::Scenery, &paths.scenery_lst),
+        ] {
+            let protos: &mut BTreeMap<_, _> = items.entry(*kind).or_default();
+            for lst in proto_lists(path)? {
+                protos.extend(fo_proto_format::build_btree(&lst));
+            }
+        }
+        let mut critters = BTreeMap::new();
+        for lst in proto_lists(&paths.critters_lst)? {
+            critters.extend(load_critter_protos(&lst)?);
+        }
+        let critter_types = if paths.critter_types.is_empty() {
+            BTreeMap::new()
+        } else {
+            load_critter_types(&paths.critter_types)?
+        };
 
         let retriever;
         #[cfg(not(feature = "sled-retriever"))]
         {
             retriever = FoData::init(&paths.client, &paths.pallette)
-                .expect("FoData loading");
+                .map_err(|err| Error::DataInit(format!("{:?}", err)))?;
         }
         #[cfg(feature = "sled-retriever")]
         {
-            retriever =
-                MyRetriever::init("D:\\fo\\test_assets\\db\\assets", &paths.pallette).unwrap();
+            retriever = MyRetriever::init("D:\\fo\\test_assets\\db\\assets", &paths.pallette)
+                .map_err(|err| Error::DataInit(format!("{:?}", err)))?;
         }
 
         /*println!(
@@ -35,25 +139,379 @@ impl Library {
         );*/
         println!("FoData loaded");
 
-        Self { items, retriever }
+        let palette = std::fs::read(&paths.pallette)
+            .map_err(|err| format!("{:?}", err))
+            .or_else(|_| {
+                retriever
+                    .file_by_path(&paths.pallette)
+                    .map(|data| data.to_vec())
+                    .map_err(|err| format!("{:?}", err))
+            })
+            .and_then(|data| Palette::parse(&data));
+        let palette = match palette {
+            Ok(palette) => Some(palette),
+            Err(err) => {
+                println!("Palette is not loaded, critters won't be drawn: {}", err);
+                None
+            }
+        };
+
+        let library = Self {
+            items,
+            critters,
+            critter_types,
+            palette,
+            retriever,
+        };
+        println!(
+            "Protos loaded: {} items, {} scenery, {} critters",
+            library.proto_count(ProtoKind::Item),
+            library.proto_count(ProtoKind::Scenery),
+            library.proto_count(ProtoKind::Critter),
+        );
+        Ok(library)
+    }
+    pub fn proto(&self, kind: ProtoKind, proto_id: u16) -> Option<Proto> {
+        match kind {
+            ProtoKind::Item => self.item_kind(kind)?.get(&proto_id).map(Proto::Item),
+            ProtoKind::Scenery => self.item_kind(kind)?.get(&proto_id).map(Proto::Scenery),
+            ProtoKind::Critter => self.critters.get(&proto_id).map(Proto::Critter),
+        }
+    }
+    /// Proto of an object of the kind. Items and scenery look into the other list only when
+    /// their own one lacks the id, some setups keep both kinds in `items_lst`.
+    pub fn kind_proto(&self, kind: ProtoKind, proto_id: u16) -> Option<Proto> {
+        let fallback = match kind {
+            ProtoKind::Item => Some(ProtoKind::Scenery),
+            ProtoKind::Scenery => Some(ProtoKind::Item),
+            ProtoKind::Critter => None,
+        };
+        self.proto(kind, proto_id)
+            .or_else(|| self.proto(fallback?, proto_id))
+    }
+    /// Proto of a map object, looked up by the kind of the object.
+    pub fn object_proto(&self, obj: &fo_map_format::Object) -> Option<Proto> {
+        self.kind_proto(ProtoKind::of_object(obj), obj.proto_id)
+    }
+    /// Every proto id of the kind, in ascending order.
+    pub fn proto_ids(&self, kind: ProtoKind) -> Vec<u16> {
+        match kind {
+            ProtoKind::Critter => self.critters.keys().copied().collect(),
+            _ => self
+                .item_kind(kind)
+                .map(|protos| protos.keys().copied().collect())
+                .unwrap_or_default(),
+        }
+    }
+    pub fn proto_count(&self, kind: ProtoKind) -> usize {
+        match kind {
+            ProtoKind::Critter => self.critters.len(),
+            _ => self.item_kind(kind).map_or(0, BTreeMap::len),
+        }
+    }
+    fn item_kind(&self, kind: ProtoKind) -> Option<&BTreeMap<u16, fo_proto_format::ProtoItem>> {
+        self.items.get(&kind)
+    }
+    pub fn critter_proto(&self, proto_id: u16) -> Option<&CritterProto> {
+        self.critters.get(&proto_id)
+    }
+    /// Critter protos are optional, without them critters are not checked nor drawn.
+    pub fn has_critter_protos(&self) -> bool {
+        !self.critters.is_empty()
     }
+    /// Item or scenery proto of a map object, `None` for critters.
     pub fn with_proto<'a>(
         &'a self,
         obj: &'a fo_map_format::Object,
     ) -> Option<(&'a fo_map_format::Object, &'a fo_proto_format::ProtoItem)> {
-        self.items.get(&obj.proto_id).map(|proto| (obj, proto))
+        self.object_proto(obj)?.as_item().map(|proto| (obj, proto))
+    }
+    pub fn with_critter_proto<'a>(
+        &'a self,
+        obj: &'a fo_map_format::Object,
+    ) -> Option<(&'a fo_map_format::Object, &'a CritterProto)> {
+        self.critters.get(&obj.proto_id).map(|proto| (obj, proto))
+    }
+    /// Conventional path of the standing (or lying, for dead and knocked out) critter sprite.
+    pub fn critter_sprite(&self, proto: &CritterProto, cond: u8) -> Option<String> {
+        let name = self.critter_types.get(&proto.base_type)?;
+        let anim = if cond == COND_LIFE { "aa" } else { "ra" };
+        Some(format!("art/critters/{}{}.frm", name, anim))
+    }
+    fn load_frm(&self, path: &str) -> Result<Frm, String> {
+        let data = self
+            .retriever
+            .file_by_path(path)
+            .map_err(|err| format!("{:?}", err))?;
+        Frm::parse(&data)
+    }
+}
+
+/// Proto lists to load for a configured path: the `.lst` file itself,
+/// every `.lst` file of a directory, or nothing when the path is empty.
+fn proto_lists(path: &str) -> Result<Vec<String>, Error> {
+    if path.is_empty() {
+        return Ok(vec![]);
+    }
+    let missing = |err: std::io::Error| Error::MissingProto {
+        path: path.to_owned(),
+        error: err.to_string(),
+    };
+    let dir = Path::new(path);
+    if !std::fs::metadata(dir).map_err(missing)?.is_dir() {
+        return Ok(vec![path.to_owned()]);
+    }
+    let mut lists: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(missing)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()
+        .map_err(missing)?;
+    lists.retain(|path| path.extension().is_some_and(|ext| ext == "lst"));
+    lists.sort();
+    Ok(lists
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
+}
+
+/// Parses `.fopro` files listed in a critter `.lst`, keeping only the fields we draw with.
+fn load_critter_protos(lst: &str) -> Result<BTreeMap<u16, CritterProto>, Error> {
+    let dir = Path::new(lst).parent().unwrap_or_else(|| Path::new(""));
+    let list = std::fs::read_to_string(lst).map_err(|err| Error::MissingProto {
+        path: lst.to_owned(),
+        error: err.to_string(),
+    })?;
+    let mut protos = BTreeMap::new();
+    for file in list.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let text = match std::fs::read_to_string(dir.join(file)) {
+            Ok(text) => text,
+            Err(err) => {
+                println!("Can't read critter proto {:?}: {}", file, err);
+                continue;
+            }
+        };
+        let mut pid = None;
+        let mut base_type = 0;
+        let mut flush = |pid: &mut Option<u16>, base_type: &mut u32| {
+            if let Some(pid) = pid.take() {
+                protos.insert(
+                    pid,
+                    CritterProto {
+                        pid,
+                        base_type: *base_type,
+                    },
+                );
+            }
+            *base_type = 0;
+        };
+        for line in text.lines().map(str::trim) {
+            if line.starts_with('[') {
+                flush(&mut pid, &mut base_type);
+                continue;
+            }
+            let mut split = line.splitn(2, '=');
+            let (key, value) = match (split.next(), split.next()) {
+                (Some(key), Some(value)) => (key.trim(), value.trim()),
+                _ => continue,
+            };
+            match key {
+                "Pid" => pid = value.parse().ok(),
+                "ST_BASE_CRTYPE" => base_type = value.parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+        flush(&mut pid, &mut base_type);
     }
+    Ok(protos)
+}
+
+/// Parses `@ <number> <name> ...` lines of `CritterTypes.cfg`.
+fn load_critter_types(path: &str) -> Result<BTreeMap<u32, String>, Error> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| Error::DataInit(format!("can't read {:?}: {}", path, err)))?;
+    Ok(text
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            if words.next()? != "@" {
+                return None;
+            }
+            let number = words.next()?.parse().ok()?;
+            let name = words.next()?.to_lowercase();
+            Some((number, name))
+        })
+        .collect())
+}
+
+/// Sprite with every frame of its animation, static images have a single frame.
+pub struct Image {
+    fps: u16,
+    frames: Vec<FrameImage>,
 }
 
-pub type Image = fo_data::RawImage;
+/// Fallout plays FRMs with zero fps at this rate.
+const DEFAULT_FPS: u16 = 10;
+
 impl Load for Image {
     fn load(path: &str, library: &Library) -> Result<Self, String> {
-        library
-            .retriever
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        match (extension.as_str(), &library.palette) {
+            ("frm", Some(palette)) => {
+                let frm = library.load_frm(path)?;
+                let frames = FrameImage::frames(&frm, 0, palette);
+                Image::new(frm.fps, frames)
+            }
+            ("fofrm", _) => library.load_fofrm(path),
+            _ => library.load_rgba(path).map(|frame| Image {
+                fps: DEFAULT_FPS,
+                frames: vec![frame],
+            }),
+        }
+    }
+}
+
+impl Image {
+    fn new(fps: u16, frames: Vec<FrameImage>) -> Result<Self, String> {
+        if frames.is_empty() {
+            return Err("Animation has no frames".into());
+        }
+        let fps = if fps == 0 { DEFAULT_FPS } else { fps };
+        Ok(Self { fps, frames })
+    }
+}
+
+/// Frames of the animation that follow the first one, which is stored as a regular image.
+pub struct Animation {
+    pub fps: u16,
+    pub frames: Vec<FrameImage>,
+}
+
+impl Library {
+    fn load_rgba(&self, path: &str) -> Result<FrameImage, String> {
+        self.retriever
             .converter()
-            .get_rgba(&path)
+            .get_rgba(path)
+            .map(|raw| FrameImage {
+                image: raw.image,
+                offset_x: raw.offset_x,
+                offset_y: raw.offset_y,
+            })
             .map_err(|err| format!("{:?}", err))
     }
+    /// Loads the first direction of a FOnline `.fofrm` animation description.
+    fn load_fofrm(&self, path: &str) -> Result<Image, String> {
+        let data = self
+            .retriever
+            .file_by_path(path)
+            .map_err(|err| format!("{:?}", err))?;
+        let text = String::from_utf8_lossy(&data);
+        let dir = path.rfind('/').map(|pos| &path[..=pos]).unwrap_or("");
+        let fofrm = FoFrm::parse(&text);
+
+        let (mut next_x, mut next_y) = (fofrm.offs_x, fofrm.offs_y);
+        let frames = fofrm
+            .frames
+            .iter()
+            .map(|(file, frame_x, frame_y)| {
+                next_x += frame_x;
+                next_y += frame_y;
+                let path = nom_prelude::make_path_conventional(&format!("{}{}", dir, file));
+                let mut frame = self.load_rgba(&path)?;
+                frame.offset_x += next_x;
+                frame.offset_y += next_y;
+                Ok(frame)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Image::new(fofrm.fps, frames)
+    }
+}
+
+/// Contents of a `.fofrm` file that matter for the first direction.
+#[derive(Debug, Default, PartialEq)]
+struct FoFrm {
+    fps: u16,
+    offs_x: i16,
+    offs_y: i16,
+    /// Frame file, relative to the `.fofrm`, and its `next_x`/`next_y` shift, in frame order.
+    frames: Vec<(String, i16, i16)>,
+}
+
+impl FoFrm {
+    fn parse(text: &str) -> Self {
+        let mut fofrm = FoFrm::default();
+        let mut frames: BTreeMap<u32, (String, i16, i16)> = BTreeMap::new();
+        for line in text.lines().map(str::trim) {
+            if line.starts_with("[dir_") && !line.starts_with("[dir_0]") {
+                break;
+            }
+            let mut split = line.splitn(2, '=');
+            let (key, value) = match (split.next(), split.next()) {
+                (Some(key), Some(value)) => (key.trim(), value.trim()),
+                _ => continue,
+            };
+            let (name, index) = match key.rfind('_') {
+                Some(pos) if key[pos + 1..].parse::<u32>().is_ok() => {
+                    (&key[..pos], key[pos + 1..].parse().unwrap_or(0))
+                }
+                _ => (key, 0),
+            };
+            let frame = frames.entry(index);
+            match name {
+                "fps" => fofrm.fps = value.parse().unwrap_or(0),
+                "offs_x" => fofrm.offs_x = value.parse().unwrap_or(0),
+                "offs_y" => fofrm.offs_y = value.parse().unwrap_or(0),
+                "frm" => frame.or_default().0 = value.to_owned(),
+                "next_x" => frame.or_default().1 = value.parse().unwrap_or(0),
+                "next_y" => frame.or_default().2 = value.parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+        fofrm.frames = frames.into_values().collect();
+        fofrm
+    }
+}
+
+/// Critter sprite facing one direction; asset path is `<frm path>#<dir>`.
+pub struct CritterImage(FrameImage);
+
+impl CritterImage {
+    pub fn asset_path(path: &str, dir: u8) -> String {
+        format!("{}#{}", path, dir)
+    }
+}
+
+impl Load for CritterImage {
+    fn load(path: &str, library: &Library) -> Result<Self, String> {
+        let mut split = path.rsplitn(2, '#');
+        let dir = split.next().and_then(|dir| dir.parse().ok()).unwrap_or(0);
+        let path = split
+            .next()
+            .ok_or_else(|| format!("No direction in {:?}", path))?;
+        let palette = library
+            .palette
+            .as_ref()
+            .ok_or_else(|| "Palette is not loaded".to_string())?;
+        // Some critters ship every direction as a separate `.fr0`..`.fr5` file.
+        let (frm, dir) = match library.load_frm(path) {
+            Ok(frm) => (frm, dir),
+            Err(err) => {
+                let separate = format!("{}{}", path.trim_end_matches('m'), dir);
+                (library.load_frm(&separate).map_err(|_| err)?, 0)
+            }
+        };
+        FrameImage::first_frame(&frm, dir, palette).map(CritterImage)
+    }
+}
+
+impl IntoComponents for CritterImage {
+    type Components = <FrameImage as IntoComponents>::Components;
+    fn into_components(self) -> Self::Components {
+        self.0.into_components()
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -65,18 +523,62 @@ pub struct ImageOffset {
 }
 
 impl IntoComponents for Image {
-    type Components = (ImageSize, ImageOffset, image::RgbaImage);
+    type Components = (ImageSize, ImageOffset, image::RgbaImage, Animation);
     fn into_components(self) -> Self::Components {
-        (
-            ImageSize(euclid::size2(
-                self.image.width() as u16,
-                self.image.height() as u16,
-            )),
-            ImageOffset {
-                x: self.offset_x,
-                y: self.offset_y,
-            },
-            self.image,
-        )
+        let mut frames = self.frames.into_iter();
+        let (size, offset, image) = frames
+            .next()
+            .expect("Image has at least one frame")
+            .into_components();
+        let animation = Animation {
+            fps: self.fps,
+            frames: frames.collect(),
+        };
+        (size, offset, image, animation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fofrm_reads_first_direction() {
+        let fofrm = FoFrm::parse(
+            "fps=8\n\
+             count=2\n\
+             offs_x=3\n\
+             offs_y=-4\n\
+             \n\
+             [dir_0]\n\
+             frm_1 = walk_1.png\n\
+             next_x_1=2\n\
+             next_y_1=-1\n\
+             frm_0=walk_0.png\n\
+             \n\
+             [dir_1]\n\
+             frm_0=other_0.png\n\
+             next_x_0=100\n\
+             frm_1=other_1.png\n",
+        );
+        assert_eq!(
+            fofrm,
+            FoFrm {
+                fps: 8,
+                offs_x: 3,
+                offs_y: -4,
+                frames: vec![
+                    ("walk_0.png".to_owned(), 0, 0),
+                    ("walk_1.png".to_owned(), 2, -1),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn fofrm_without_directions() {
+        let fofrm = FoFrm::parse("fps=abc\r\nfrm=single.png\r\nnext_x=5\r\n");
+        assert_eq!(fofrm.fps, 0);
+        assert_eq!(fofrm.frames, vec![("single.png".to_owned(), 5, 0)]);
     }
 }
@@ -0,0 +1,101 @@
+use crate::Geometry;
+use std::collections::HashMap;
+
+/// Light emitted by a placed object, with parameters taken from its proto.
+#[derive(Debug, Clone)]
+pub struct LightSource {
+    pub hex_x: u16,
+    pub hex_y: u16,
+    /// Percents of full brightness at the source hex.
+    pub intensity: i32,
+    /// Radius in hexes.
+    pub distance: u32,
+    /// `0xRRGGBB`, zero means white.
+    pub color: u32,
+    /// `LightFlags` of the proto. Only `LIGHT_INVERSE` is honoured; the disabled directions
+    /// and `LIGHT_GLOBAL` are not supported and the light spreads evenly in every direction.
+    pub flags: u32,
+}
+
+/// Light grows towards the edge of the radius instead of fading out.
+const LIGHT_INVERSE: u32 = 0x80;
+
+/// Light accumulated per hex from every source; walls don't block it.
+pub struct LightMap(HashMap<(u16, u16), [f32; 3]>);
+
+impl LightMap {
+    pub fn new(geometry: Geometry, sources: &[LightSource]) -> Self {
+        let mut hexes = HashMap::new();
+        for source in sources {
+            let intensity = (source.intensity.abs().min(100) as f32) / 100.0;
+            if intensity == 0.0 {
+                continue;
+            }
+            let color = if source.color == 0 {
+                [1.0; 3]
+            } else {
+                [
+                    ((source.color >> 16) & 0xFF) as f32 / 255.0,
+                    ((source.color >> 8) & 0xFF) as f32 / 255.0,
+                    (source.color & 0xFF) as f32 / 255.0,
+                ]
+            };
+            let distance = source.distance as i32;
+            let (x0, y0) = (source.hex_x as i32, source.hex_y as i32);
+            for y in (y0 - distance).max(0)..=y0 + distance {
+                for x in (x0 - distance).max(0)..=x0 + distance {
+                    let dist =
+                        geometry.distance((x0 as u16, y0 as u16), (x as u16, y as u16)) as i32;
+                    if dist > distance {
+                        continue;
+                    }
+                    let falloff = if source.flags & LIGHT_INVERSE != 0 {
+                        (dist + 1) as f32 / (distance + 1) as f32
+                    } else {
+                        1.0 - dist as f32 / (distance + 1) as f32
+                    };
+                    let light = hexes.entry((x as u16, y as u16)).or_insert([0.0; 3]);
+                    for (channel, color) in light.iter_mut().zip(&color) {
+                        *channel += color * intensity * falloff;
+                    }
+                }
+            }
+        }
+        LightMap(hexes)
+    }
+    /// Light of the hex, normalized and clamped to full brightness.
+    pub fn get(&self, hex_x: u16, hex_y: u16) -> [u8; 4] {
+        let [r, g, b] = self.0.get(&(hex_x, hex_y)).copied().unwrap_or([0.0; 3]);
+        let norm = |channel: f32| (channel.min(1.0) * 255.0) as u8;
+        [norm(r), norm(g), norm(b), 255]
+    }
+}
+
+/// Minutes of the day at which `DAY_COLORS` are reached, engine defaults.
+const DAY_TIME: [u32; 4] = [300, 600, 1140, 1380];
+/// Ambient colors at `DAY_TIME` points, `128` is the neutral level.
+const DAY_COLORS: [[u8; 3]; 4] = [[18, 18, 53], [128, 128, 128], [103, 95, 86], [51, 40, 29]];
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// Ambient color at the given in-game hour, interpolated like `GetColorDay` of the engine.
+pub fn day_color(hour: f32) -> [f32; 3] {
+    let minute = (hour.rem_euclid(24.0) * 60.0) as u32 % MINUTES_PER_DAY;
+    let index = DAY_TIME
+        .iter()
+        .rposition(|&time| minute >= time)
+        .unwrap_or(DAY_TIME.len() - 1);
+    let next = (index + 1) % DAY_TIME.len();
+    let duration = (DAY_TIME[next] + MINUTES_PER_DAY - DAY_TIME[index]) % MINUTES_PER_DAY;
+    let passed = (minute + MINUTES_PER_DAY - DAY_TIME[index]) % MINUTES_PER_DAY;
+    let progress = passed as f32 / duration.max(1) as f32;
+
+    let mut color = [0.0; 3];
+    for (channel, (from, to)) in color
+        .iter_mut()
+        .zip(DAY_COLORS[index].iter().zip(&DAY_COLORS[next]))
+    {
+        let value = *from as f32 + (*to as f32 - *from as f32) * progress;
+        *channel = (value / 128.0).min(1.0);
+    }
+    color
+}
@@ -0,0 +1,121 @@
+use crate::{
+    overlay::{hex_fill, hex_outline, label, line, OverlayVertex},
+    Geometry, Library,
+};
+
+/// Map entrance, the number is taken from `ToEntire`.
+const SP_GRID_ENTIRE: u16 = 3853;
+/// Camera scroll limit.
+const SP_MISC_SCRBLOCK: u16 = 4012;
+
+const ENTIRE_COLOR: [u8; 4] = [0, 255, 64, 128];
+const SCROLL_BLOCK_COLOR: [u8; 4] = [255, 0, 255, 128];
+const HIDDEN_COLOR: [u8; 4] = [255, 160, 0, 255];
+const LABEL_COLOR: [u8; 4] = [255, 255, 255, 255];
+/// Scroll blockers further apart than this are not joined by a line.
+const SCROLL_BLOCK_REACH: i32 = 8 * 32;
+
+/// Object that is invisible in the game but matters for map makers.
+#[derive(Debug, Clone)]
+pub struct Marker {
+    pub hex_x: u16,
+    pub hex_y: u16,
+    pub kind: MarkerKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarkerKind {
+    Entire(u32),
+    ScrollBlock,
+    /// Hidden or sprite-less object, with its proto id.
+    Hidden(u16),
+}
+
+/// Markers for entires, scroll blockers and every object that is not drawn as a sprite.
+pub fn collect(objects: &[fo_map_format::Object], library: &Library) -> Vec<Marker> {
+    use fo_defines_fo4rp::fos;
+
+    objects
+        .iter()
+        .filter(|obj| !matches!(obj.kind, fo_map_format::ObjectKind::Critter(_)))
+        .filter_map(|obj| {
+            let kind = match obj.proto_id {
+                SP_GRID_ENTIRE => {
+                    let number = match &obj.kind {
+                        fo_map_format::ObjectKind::Scenery(scenery) => {
+                            scenery.to_entire.unwrap_or(0)
+                        }
+                        _ => 0,
+                    };
+                    MarkerKind::Entire(number as u32)
+                }
+                SP_MISC_SCRBLOCK => MarkerKind::ScrollBlock,
+                proto_id => {
+                    let hidden = library.with_proto(obj).map_or(true, |(_, proto)| {
+                        (proto.Flags.unwrap_or(0) & fos::ITEM_HIDDEN) != 0
+                    });
+                    if !hidden && obj.kind.anim().is_some() {
+                        return None;
+                    }
+                    MarkerKind::Hidden(proto_id)
+                }
+            };
+            Some(Marker {
+                hex_x: obj.map_x.unwrap_or(0),
+                hex_y: obj.map_y.unwrap_or(0),
+                kind,
+            })
+        })
+        .collect()
+}
+
+/// Overlay geometry for the markers: filled triangles and lines.
+pub fn glyphs(geometry: Geometry, markers: &[Marker]) -> (Vec<OverlayVertex>, Vec<OverlayVertex>) {
+    let mut fill = hex_fill(
+        geometry,
+        markers.iter().filter_map(|marker| {
+            let color = match marker.kind {
+                MarkerKind::Entire(_) => ENTIRE_COLOR,
+                MarkerKind::ScrollBlock => SCROLL_BLOCK_COLOR,
+                MarkerKind::Hidden(_) => return None,
+            };
+            Some(((marker.hex_x, marker.hex_y), color))
+        }),
+    );
+    let mut lines = vec![];
+
+    for marker in markers {
+        let (x, y) = geometry.position(marker.hex_x, marker.hex_y);
+        match marker.kind {
+            MarkerKind::Entire(number) => {
+                fill.extend(label(&number.to_string(), x, y - 16, 2, LABEL_COLOR));
+            }
+            MarkerKind::Hidden(proto_id) => {
+                lines.extend(hex_outline(geometry, x, y, HIDDEN_COLOR));
+                fill.extend(label(&proto_id.to_string(), x, y - 12, 1, HIDDEN_COLOR));
+            }
+            MarkerKind::ScrollBlock => {}
+        }
+    }
+
+    // Scroll blockers are placed as chains, join every one to its closest neighbor.
+    let blockers: Vec<_> = markers
+        .iter()
+        .filter(|marker| marker.kind == MarkerKind::ScrollBlock)
+        .map(|marker| geometry.position(marker.hex_x, marker.hex_y))
+        .collect();
+    for (i, from) in blockers.iter().enumerate() {
+        let distance = |to: &(i32, i32)| (to.0 - from.0).pow(2) + (to.1 - from.1).pow(2);
+        let closest = blockers
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, to)| to)
+            .min_by_key(|to| distance(to));
+        if let Some(to) = closest.filter(|to| distance(to) <= SCROLL_BLOCK_REACH.pow(2)) {
+            lines.extend(line(*from, *to, SCROLL_BLOCK_COLOR));
+        }
+    }
+
+    (fill, lines)
+}
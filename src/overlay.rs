@@ -0,0 +1,247 @@
+use crate::{sprite_map::shader_module_from_file, Geometry, Wgpu};
+use std::{collections::HashSet, path::Path};
+use zerocopy::AsBytes;
+
+#[repr(C)]
+#[derive(AsBytes, Clone, Copy)]
+pub struct OverlayVertex {
+    pub pos: [f32; 2],
+    pub color: [u8; 4],
+}
+
+impl OverlayVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<OverlayVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Uchar4Norm,
+                },
+            ],
+        }
+    }
+}
+
+/// Flat colored geometry drawn over the map in map pixel coordinates.
+pub struct Overlay {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    vertices: u32,
+}
+
+impl Overlay {
+    pub fn new(
+        wgpu: &Wgpu,
+        format: wgpu::TextureFormat,
+        shaders: &Path,
+        topology: wgpu::PrimitiveTopology,
+        vertices: &[OverlayVertex],
+    ) -> Self {
+        use wgpu::util::DeviceExt;
+        let vertex_buffer = wgpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Overlay Vertex Buffer"),
+                contents: vertices.as_bytes(),
+                usage: wgpu::BufferUsage::VERTEX,
+            });
+        Self {
+            pipeline: overlay_pipeline(wgpu, format, shaders, topology),
+            vertex_buffer,
+            vertices: vertices.len() as u32,
+        }
+    }
+    /// Expects the uniform bind group to be already set.
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        if self.vertices == 0 {
+            return;
+        }
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw(0..self.vertices, 0..1);
+    }
+}
+
+/// Outlines of every hex of the map as a line list, shared edges are emitted once.
+pub fn hex_grid(geometry: Geometry, width: u16, height: u16, color: [u8; 4]) -> Vec<OverlayVertex> {
+    let corners = geometry.corners();
+    let mut edges = HashSet::new();
+    let mut vertices = vec![];
+    for hex_y in 0..height {
+        for hex_x in 0..width {
+            let (x, y) = geometry.position(hex_x, hex_y);
+            for (i, from) in corners.iter().enumerate() {
+                let to = corners[(i + 1) % corners.len()];
+                let from = (x + from.0, y + from.1);
+                let to = (x + to.0, y + to.1);
+                let edge = if from < to { (from, to) } else { (to, from) };
+                if edges.insert(edge) {
+                    for (x, y) in [from, to].iter() {
+                        vertices.push(OverlayVertex {
+                            pos: [*x as f32, *y as f32],
+                            color,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    vertices
+}
+
+/// Filled hexes as a triangle list, triangles are fanned from the top corner.
+pub fn hex_fill(
+    geometry: Geometry,
+    hexes: impl Iterator<Item = ((u16, u16), [u8; 4])>,
+) -> Vec<OverlayVertex> {
+    let corners = geometry.corners();
+    let mut vertices = vec![];
+    for ((hex_x, hex_y), color) in hexes {
+        let (x, y) = geometry.position(hex_x, hex_y);
+        let corner = |i: usize| {
+            let (dx, dy) = corners[i];
+            OverlayVertex {
+                pos: [(x + dx) as f32, (y + dy) as f32],
+                color,
+            }
+        };
+        for i in 1..corners.len() - 1 {
+            vertices.extend_from_slice(&[corner(0), corner(i), corner(i + 1)]);
+        }
+    }
+    vertices
+}
+
+/// Single hex outline as a line list, `x` and `y` are the hex center.
+pub fn hex_outline(geometry: Geometry, x: i32, y: i32, color: [u8; 4]) -> Vec<OverlayVertex> {
+    let corners = geometry.corners();
+    (0..corners.len())
+        .flat_map(|i| {
+            let (x0, y0) = corners[i];
+            let (x1, y1) = corners[(i + 1) % corners.len()];
+            line((x + x0, y + y0), (x + x1, y + y1), color)
+        })
+        .collect()
+}
+
+pub fn line(from: (i32, i32), to: (i32, i32), color: [u8; 4]) -> Vec<OverlayVertex> {
+    [from, to]
+        .iter()
+        .map(|(x, y)| OverlayVertex {
+            pos: [*x as f32, *y as f32],
+            color,
+        })
+        .collect()
+}
+
+/// 3x5 bitmap digits, one row per byte, high bit on the left.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b011, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Digits of `text` as a triangle list, centered at `x` with the bottom at `y`.
+/// Every font pixel is a `scale` sized square, other characters are skipped.
+pub fn label(text: &str, x: i32, y: i32, scale: i32, color: [u8; 4]) -> Vec<OverlayVertex> {
+    let digits: Vec<_> = text.chars().filter_map(|c| c.to_digit(10)).collect();
+    let advance = 4 * scale;
+    let left = x - (digits.len() as i32 * advance - scale) / 2;
+    let top = y - 5 * scale;
+    let mut vertices = vec![];
+    for (n, digit) in digits.iter().enumerate() {
+        for (row, bits) in DIGITS[*digit as usize].iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                let x0 = (left + n as i32 * advance + column * scale) as f32;
+                let y0 = (top + row as i32 * scale) as f32;
+                let (x1, y1) = (x0 + scale as f32, y0 + scale as f32);
+                let vertex = |x, y| OverlayVertex { pos: [x, y], color };
+                vertices.extend_from_slice(&[
+                    vertex(x0, y0),
+                    vertex(x1, y0),
+                    vertex(x1, y1),
+                    vertex(x0, y0),
+                    vertex(x1, y1),
+                    vertex(x0, y1),
+                ]);
+            }
+        }
+    }
+    vertices
+}
+
+fn overlay_pipeline(
+    wgpu: &Wgpu,
+    format: wgpu::TextureFormat,
+    shaders: &Path,
+    topology: wgpu::PrimitiveTopology,
+) -> wgpu::RenderPipeline {
+    let vs_module = shader_module_from_file(&wgpu.device, &shaders.join("overlay.vert.spv"));
+    let fs_module = shader_module_from_file(&wgpu.device, &shaders.join("overlay.frag.spv"));
+
+    let pipeline_layout = wgpu
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&wgpu.uniform_layout],
+            push_constant_ranges: &[],
+        });
+
+    let color_states = &[wgpu::ColorTargetState {
+        format,
+        color_blend: wgpu::BlendState {
+            src_factor: wgpu::BlendFactor::SrcAlpha,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha_blend: wgpu::BlendState {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Max,
+        },
+        write_mask: wgpu::ColorWrite::ALL,
+    }];
+
+    wgpu.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[OverlayVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: color_states,
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        })
+}
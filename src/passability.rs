@@ -0,0 +1,58 @@
+use crate::Library;
+use std::collections::BTreeMap;
+
+/// What the items on a hex let through.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HexBlock {
+    /// Critters can't walk onto the hex.
+    pub blocks_move: bool,
+    /// Shots and thrown items stop at the hex.
+    pub blocks_shoot: bool,
+}
+
+/// Blocking data of every hex that has at least one item on it, hidden blockers included.
+///
+/// Critters and items inside containers are skipped, they are not items lying on the hex.
+pub fn collect(
+    objects: &[fo_map_format::Object],
+    library: &Library,
+) -> BTreeMap<(u16, u16), HexBlock> {
+    use fo_defines_fo4rp::fos;
+
+    let mut hexes = BTreeMap::new();
+    let items = objects
+        .iter()
+        .filter(|obj| !matches!(obj.kind, fo_map_format::ObjectKind::Critter(_)))
+        .filter(|obj| obj.container_uid.is_none())
+        .filter_map(|obj| library.with_proto(obj));
+    for (obj, proto) in items {
+        let flags = proto.Flags.unwrap_or(0);
+        let hex = (obj.map_x.unwrap_or(0), obj.map_y.unwrap_or(0));
+        let block = hexes.entry(hex).or_insert(HexBlock {
+            blocks_move: false,
+            blocks_shoot: false,
+        });
+        // The engine checks `IsPassed` and `IsRaked` on their own.
+        if (flags & fos::ITEM_NO_BLOCK) == 0 {
+            block.blocks_move = true;
+        }
+        if (flags & fos::ITEM_SHOOT_THRU) == 0 {
+            block.blocks_shoot = true;
+        }
+    }
+    hexes.retain(|_, block| block.blocks_move || block.blocks_shoot);
+    hexes
+}
+
+impl HexBlock {
+    /// Overlay color: red for walls, yellow for hexes that can be shot through,
+    /// blue for hexes that can be walked on but stop shots.
+    pub fn color(self) -> [u8; 4] {
+        match (self.blocks_move, self.blocks_shoot) {
+            (true, true) => [255, 0, 0, 112],
+            (true, false) => [255, 255, 0, 112],
+            (false, true) => [0, 128, 255, 112],
+            (false, false) => [0, 0, 0, 0],
+        }
+    }
+}
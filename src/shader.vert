@@ -3,11 +3,14 @@
 layout(location=0) in vec2 a_position;
 layout(location=1) in vec2 a_size;
 layout(location=2) in uvec4 a_tex;
+layout(location=3) in vec4 a_light;
 
 layout(location=0) out vec2 v_tex_coords;
+layout(location=1) out vec3 v_tint;
 
 layout(set = 0, binding = 0) uniform Uniforms {
     mat4 projection_matrix;
+    vec4 ambient;
 };
 
 out gl_PerVertex {
@@ -35,6 +38,7 @@ void main() {
         case 4: v_tex_coords = vec2(a_tex.bg); break;
         case 5: v_tex_coords = vec2(a_tex.rg); break;
     }
+    v_tint = min(ambient.rgb + a_light.rgb, vec3(1.0));
     vec4 position = vec4(quad*a_size + a_position, 0.0, 1.0);
     gl_Position = projection_matrix*position;
 }
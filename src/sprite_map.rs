@@ -1,19 +1,292 @@
 use crate::{
-    AssetKey, Assets, Config, Image, ImageOffset, ImageSize, Library, MaterialId, SizedBuffer,
-    SizedTexture, SpriteUniforms, TextureView, Wgpu,
+    library::{COND_DEAD, COND_LIFE},
+    light::{day_color, LightMap, LightSource},
+    markers::{self, Marker},
+    overlay::{hex_fill, hex_grid, Overlay},
+    passability::{self, HexBlock},
+    Animation, AnimationViews, AssetKey, Assets, Config, CritterImage, Error, Geometry, Image,
+    ImageOffset, ImageSize, Library, MaterialId, ProtoKind, Result, SizedBuffer, SizedTexture,
+    SpriteUniforms, TextureView, Wgpu,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::Path,
 };
-use std::path::Path;
 use zerocopy::AsBytes;
 
+/// Roofs are raised above floor tiles of the same hex, as in the engine (`ROOF_OY - TILE_OY`).
+const ROOF_OFFSET_Y: i32 = -100;
+
 #[derive(Debug)]
 pub struct SpriteMap {
     rect: AABB,
     tiles: Vec<Sprite>,
     objects: Vec<Sprite>,
+    roofs: Vec<Sprite>,
+    lights: Vec<LightSource>,
+    /// Every object of the map file, by index.
+    infos: Vec<ObjectInfo>,
+    blocks: BTreeMap<(u16, u16), HexBlock>,
+    markers: Vec<Marker>,
+    /// Time of day from the map header, `None` when the map follows global time.
+    hour: Option<f32>,
+    /// Map size in hexes, from the header.
+    hex_width: u16,
+    hex_height: u16,
+    geometry: Geometry,
     //assets: Assets<Image, WgpuTexture>,
 }
 
-#[derive(Debug)]
+/// Group of sprites that can be hidden as a whole.
+///
+/// Scenery, walls, items and critters are sorted together by draw order, so draw calls
+/// are split wherever the layer changes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    /// Floor tiles.
+    Tiles,
+    Scenery,
+    Walls,
+    Items,
+    Critters,
+    Roofs,
+    /// Grid, passability and mapper markers, each still has its own switch.
+    Overlays,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 7] = [
+        Layer::Tiles,
+        Layer::Scenery,
+        Layer::Walls,
+        Layer::Items,
+        Layer::Critters,
+        Layer::Roofs,
+        Layer::Overlays,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layer::Tiles => "tiles",
+            Layer::Scenery => "scenery",
+            Layer::Walls => "walls",
+            Layer::Items => "items",
+            Layer::Critters => "critters",
+            Layer::Roofs => "roofs",
+            Layer::Overlays => "overlays",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|layer| layer.name() == name)
+    }
+
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Set of visible layers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Layers(u32);
+
+impl Layers {
+    pub fn all() -> Self {
+        Layer::ALL
+            .iter()
+            .fold(Self::none(), |layers, layer| layers.with(*layer, true))
+    }
+    pub fn none() -> Self {
+        Layers(0)
+    }
+    pub fn contains(self, layer: Layer) -> bool {
+        self.0 & layer.bit() != 0
+    }
+    pub fn with(mut self, layer: Layer, visible: bool) -> Self {
+        self.set(layer, visible);
+        self
+    }
+    pub fn set(&mut self, layer: Layer, visible: bool) {
+        if visible {
+            self.0 |= layer.bit();
+        } else {
+            self.0 &= !layer.bit();
+        }
+    }
+    pub fn toggle(&mut self, layer: Layer) {
+        self.0 ^= layer.bit();
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Sprite found under a map pixel.
+#[derive(Debug, Clone)]
+pub struct PickedSprite {
+    pub layer: Layer,
+    /// Index of the sprite in its group of tiles, objects or roofs, in draw order.
+    pub index: usize,
+    pub hex_x: u16,
+    pub hex_y: u16,
+    pub path: Option<String>,
+    /// Index of the map object, see `SpriteMapRenderer::object`.
+    pub object: Option<usize>,
+}
+
+/// Drawn sprite and where it ended up in the output image, see `SpriteMapRenderer::placements`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SpritePlacement {
+    pub layer: Layer,
+    /// Rectangle of the shown animation frame in output image pixels.
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub hex_x: u16,
+    pub hex_y: u16,
+    /// `None` for tiles.
+    pub proto_id: Option<u16>,
+    /// Index of the map object, `None` for tiles.
+    pub object: Option<usize>,
+    pub path: Option<String>,
+}
+
+/// File format of the sprite list written next to a rendered PNG.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpriteSidecar {
+    Json,
+    Csv,
+}
+
+impl SpriteSidecar {
+    pub fn extension(self) -> &'static str {
+        match self {
+            SpriteSidecar::Json => "json",
+            SpriteSidecar::Csv => "csv",
+        }
+    }
+    /// Writes `placements` to `path`, one entry or row per sprite in draw order.
+    pub fn write(self, path: &Path, placements: &[SpritePlacement]) -> Result<()> {
+        let text = match self {
+            SpriteSidecar::Json => serde_json::to_string_pretty(placements)
+                .map_err(|err| Error::output(path.display(), err))?,
+            SpriteSidecar::Csv => {
+                use std::fmt::Write;
+
+                let mut text =
+                    String::from("layer,x,y,width,height,hex_x,hex_y,proto_id,object,path\n");
+                for sprite in placements {
+                    let optional = |value: Option<usize>| {
+                        value.map(|value| value.to_string()).unwrap_or_default()
+                    };
+                    writeln!(
+                        text,
+                        "{},{},{},{},{},{},{},{},{},\"{}\"",
+                        sprite.layer.name(),
+                        sprite.x,
+                        sprite.y,
+                        sprite.width,
+                        sprite.height,
+                        sprite.hex_x,
+                        sprite.hex_y,
+                        optional(sprite.proto_id.map(usize::from)),
+                        optional(sprite.object),
+                        sprite.path.as_deref().unwrap_or("").replace('"', "\"\""),
+                    )
+                    .unwrap();
+                }
+                text
+            }
+        };
+        std::fs::write(path, text).map_err(|err| Error::output(path.display(), err))
+    }
+}
+
+/// Moment of sprite animations to show.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AnimationTime {
+    /// Same frame index for every animation, wrapped by its length.
+    Frame(usize),
+    /// Seconds since the start, each animation plays at its own fps.
+    Seconds(f32),
+}
+
+impl Default for AnimationTime {
+    fn default() -> Self {
+        AnimationTime::Frame(0)
+    }
+}
+
+/// Area of the map that ends up in the output.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Bounds {
+    /// Extent of the drawn sprites, changes whenever an edge object does.
+    #[default]
+    Sprites,
+    /// Every hex of the map, by the header size.
+    Header,
+    /// Hexes between two corners, both included.
+    Hexes { from: (u16, u16), to: (u16, u16) },
+    /// Map pixels between two corners, in the coordinates of `Geometry::position`.
+    Pixels { from: (i32, i32), to: (i32, i32) },
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub layers: Layers,
+    pub animation: AnimationTime,
+    /// Modulate sprites with ambient and object light.
+    pub lighting: bool,
+    /// Global light level, `1.0` is full daylight.
+    pub ambient: f32,
+    /// In-game hour for the day/night tint, defaults to the map header time.
+    pub hour: Option<f32>,
+    /// Draw hex outlines over floor tiles.
+    pub grid: bool,
+    /// Color hexes that block movement or shots.
+    pub passability: bool,
+    /// Mapper mode: show entires, scroll blockers and hidden objects.
+    pub markers: bool,
+    /// Grid layout of this map, overrides the config.
+    pub geometry: Option<Geometry>,
+    pub bounds: Bounds,
+    /// Pixels added around the bounds on every side.
+    pub padding: u32,
+    /// Output pixels per map pixel, fits the bounds into `size` when not set.
+    pub scale: Option<f32>,
+    /// Output image size, the bounds are centered in it.
+    pub size: Option<(u32, u32)>,
+    /// Sprite list to write next to the PNG.
+    pub sprites: Option<SpriteSidecar>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            layers: Layers::all(),
+            animation: AnimationTime::default(),
+            lighting: true,
+            ambient: 1.0,
+            hour: None,
+            grid: false,
+            passability: false,
+            markers: false,
+            geometry: None,
+            bounds: Bounds::Sprites,
+            padding: 0,
+            scale: None,
+            size: None,
+            sprites: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 struct Sprite {
     hex_x: u16,
     hex_y: u16,
@@ -21,46 +294,245 @@ struct Sprite {
     y: i32,
     z: i32,
     asset: AssetKey,
+    /// Index of the map object this sprite was made from, `None` for tiles.
+    object: Option<usize>,
+    layer: Layer,
+}
+
+/// Map object data kept for inspection, everything else of `fo_map_format::Object` is dropped.
+#[derive(Debug, Clone)]
+pub struct ObjectInfo {
+    /// Position in the object list of the map file.
+    pub index: usize,
+    pub proto_id: u16,
+    pub hex_x: u16,
+    pub hex_y: u16,
+    pub offset_x: i32,
+    pub offset_y: i32,
+    pub dir: Option<u8>,
+    pub uid: Option<u32>,
+    /// UID of the container or critter holding this item.
+    pub container_uid: Option<u32>,
+    pub script_name: Option<String>,
+    pub func_name: Option<String>,
+    /// Map picture that replaces the proto's `PicMap`.
+    pub pic_map: Option<String>,
+    pub kind: ProtoKind,
+}
+
+impl ObjectInfo {
+    fn new(index: usize, obj: &fo_map_format::Object) -> Self {
+        use fo_map_format::Offset;
+
+        let (offset_x, offset_y) = obj.offset();
+        Self {
+            index,
+            proto_id: obj.proto_id,
+            hex_x: obj.map_x.unwrap_or(0),
+            hex_y: obj.map_y.unwrap_or(0),
+            offset_x,
+            offset_y,
+            dir: obj.dir,
+            uid: obj.uid,
+            container_uid: obj.container_uid,
+            script_name: obj.script_name.as_ref().map(|name| name.to_string()),
+            func_name: obj.func_name.as_ref().map(|name| name.to_string()),
+            pic_map: pic_map_override(obj),
+            kind: ProtoKind::of_object(obj),
+        }
+    }
+}
+
+/// Problems found while opening a map that don't stop it from rendering.
+#[derive(Debug, Clone, Default)]
+pub struct MapDiagnostics {
+    /// Objects that are not drawn because their proto is not loaded.
+    pub unknown_protos: Vec<UnknownProto>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnknownProto {
+    /// Position in the object list of the map file.
+    pub index: usize,
+    pub proto_id: u16,
+    pub hex_x: u16,
+    pub hex_y: u16,
+    pub kind: ProtoKind,
+}
+
+impl MapDiagnostics {
+    fn collect(infos: &[ObjectInfo], library: &Library) -> Self {
+        let unknown_protos = infos
+            .iter()
+            .filter(|info| {
+                let checked = info.kind != ProtoKind::Critter || library.has_critter_protos();
+                checked && library.kind_proto(info.kind, info.proto_id).is_none()
+            })
+            .map(|info| UnknownProto {
+                index: info.index,
+                proto_id: info.proto_id,
+                hex_x: info.hex_x,
+                hex_y: info.hex_y,
+                kind: info.kind,
+            })
+            .collect();
+        Self { unknown_protos }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.unknown_protos.is_empty()
+    }
+}
+
+impl fmt::Display for MapDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.unknown_protos.is_empty() {
+            writeln!(
+                f,
+                "{} objects with unknown protos:",
+                self.unknown_protos.len()
+            )?;
+        }
+        for unknown in &self.unknown_protos {
+            let kind = match unknown.kind {
+                ProtoKind::Item => "item",
+                ProtoKind::Scenery => "scenery",
+                ProtoKind::Critter => "critter",
+            };
+            writeln!(
+                f,
+                "  #{} {} proto {} at hex {}:{}",
+                unknown.index, kind, unknown.proto_id, unknown.hex_x, unknown.hex_y
+            )?;
+        }
+        Ok(())
+    }
 }
 
 impl SpriteMap {
-    pub fn open(path: &str, library: &Library, assets: &mut Assets) -> Self {
-        use draw_geometry::fo as geometry;
+    pub fn open(
+        path: &str,
+        geometry: Geometry,
+        library: &Library,
+        assets: &mut Assets,
+    ) -> Result<(Self, MapDiagnostics)> {
+        use draw_geometry::fo::{DrawOrderType, DRAW_ORDER_FLAT};
         use fo_map_format::Offset;
-        use primitives::Hex;
 
         fo_map_format::verbose_read_file(
             path,
-            |_, res| {
-                let map = res.unwrap().1;
+            |_, res| -> Result<(Self, MapDiagnostics)> {
+                let map = res.map_err(|err| Error::map_parse(path, err))?.1;
 
                 let tiles = map
                     .tiles
                     .0
                     .iter()
-                    .filter(|tile| !tile.is_roof)
-                    .map(|tile| {
+                    .map(|tile| -> Result<_> {
                         let (hex_x, hex_y) = (tile.hex_x, tile.hex_y);
                         let (offset_x, offset_y) = tile.offset();
-                        let (x, y) = (hex_x as i32, hex_y as i32);
-                        let (x, y) = (
-                            /*x = */ y * 16 - x * 24 - 24 + offset_x,
-                            /*y = */ y * 12 + x * 6 + 24 + offset_y,
-                        );
-                        let z = geometry::draw_order_pos_int(
-                            geometry::DRAW_ORDER_FLAT + tile.layer.unwrap_or(0) as u32,
-                            Hex::new(tile.hex_x, tile.hex_y),
-                        )
-                        .unwrap_or(0);
-
-                        let asset = assets.upsert_path::<Image>(
-                            map.tiles
-                                .1
-                                .to_path
-                                .get(&tile.hash)
-                                .expect("Hash must have related conventional path"),
+                        let (x, y) = geometry.tile_position(hex_x, hex_y);
+                        let (x, y) = (x + offset_x, y + offset_y);
+                        let y = if tile.is_roof { y + ROOF_OFFSET_Y } else { y };
+                        let z = geometry.draw_order(
+                            DRAW_ORDER_FLAT + tile.layer.unwrap_or(0) as u32,
+                            hex_x,
+                            hex_y,
                         );
 
+                        let tile_path = map.tiles.1.to_path.get(&tile.hash).ok_or_else(|| {
+                            Error::UnknownTileHash {
+                                hash: format!("{:?}", tile.hash),
+                                hex_x,
+                                hex_y,
+                            }
+                        })?;
+                        let asset = assets.upsert_path::<Image>(tile_path);
+
+                        Ok((
+                            tile.is_roof,
+                            Sprite {
+                                hex_x,
+                                hex_y,
+                                x,
+                                y,
+                                z,
+                                asset,
+                                object: None,
+                                layer: if tile.is_roof {
+                                    Layer::Roofs
+                                } else {
+                                    Layer::Tiles
+                                },
+                            },
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let (roofs, tiles): (Vec<_>, Vec<_>) =
+                    tiles.into_iter().partition(|(is_roof, _)| *is_roof);
+                let roofs = roofs.into_iter().map(|(_, sprite)| sprite).collect();
+                let tiles = tiles.into_iter().map(|(_, sprite)| sprite).collect();
+                // Critter PIDs live apart from item ones and contained items are not on the map.
+                let lights = map
+                    .objects
+                    .0
+                    .iter()
+                    .filter(|obj| !matches!(obj.kind, fo_map_format::ObjectKind::Critter(_)))
+                    .filter(|obj| obj.container_uid.is_none())
+                    .filter_map(|obj| library.with_proto(obj))
+                    .filter(|(_obj, proto)| {
+                        (proto.Flags.unwrap_or(0) & fo_defines_fo4rp::fos::ITEM_LIGHT) != 0
+                    })
+                    .map(|(obj, proto)| LightSource {
+                        hex_x: obj.map_x.unwrap_or(0),
+                        hex_y: obj.map_y.unwrap_or(0),
+                        intensity: proto.LightIntensity.unwrap_or(0) as i32,
+                        distance: proto.LightDistance.unwrap_or(0) as u32,
+                        color: proto.LightColor.unwrap_or(0) as u32,
+                        flags: proto.LightFlags.unwrap_or(0) as u32,
+                    })
+                    .collect();
+                let infos: Vec<_> = map
+                    .objects
+                    .0
+                    .iter()
+                    .enumerate()
+                    .map(|(index, obj)| ObjectInfo::new(index, obj))
+                    .collect();
+                let diagnostics = MapDiagnostics::collect(&infos, library);
+                let blocks = passability::collect(&map.objects.0, library);
+                let markers = markers::collect(&map.objects.0, library);
+                let critters = map
+                    .objects
+                    .0
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, obj)| match &obj.kind {
+                        fo_map_format::ObjectKind::Critter(critter) => Some((index, obj, critter)),
+                        _ => None,
+                    })
+                    .filter_map(|(index, obj, critter)| {
+                        let (obj, proto) = library.with_critter_proto(obj)?;
+                        let cond = critter.cond.unwrap_or(COND_LIFE);
+                        let path = library.critter_sprite(proto, cond)?;
+                        Some((index, obj, cond, path))
+                    })
+                    .map(|(index, obj, cond, path)| {
+                        let (hex_x, hex_y) = (obj.map_x.unwrap_or(0), obj.map_y.unwrap_or(0));
+                        let (offset_x, offset_y) = obj.offset();
+                        let (x, y) = geometry.position(hex_x, hex_y);
+                        let (x, y) = (x + offset_x, y + offset_y);
+
+                        let draw_order = if cond == COND_DEAD {
+                            DrawOrderType::DRAW_ORDER_DEAD_CRITTER
+                        } else {
+                            DrawOrderType::DRAW_ORDER_CRITTER
+                        };
+                        let z = geometry.draw_order(draw_order as u32, hex_x, hex_y);
+
+                        let dir = obj.dir.unwrap_or(0);
+                        let asset = assets
+                            .upsert_path::<CritterImage>(&CritterImage::asset_path(&path, dir));
+
                         Sprite {
                             hex_x,
                             hex_y,
@@ -68,39 +540,44 @@ impl SpriteMap {
                             y,
                             z,
                             asset,
+                            object: Some(index),
+                            layer: Layer::Critters,
                         }
                     })
-                    .collect();
-                let objects = map
+                    .collect::<Vec<_>>();
+                let mut objects: Vec<_> = map
                     .objects
                     .0
                     .iter()
+                    .enumerate()
                     //.filter(|obj| obj.is_scenery())
-                    .filter(|obj| obj.kind.anim().is_some())
-                    .filter_map(|obj| library.with_proto(obj))
-                    .filter(|(_obj, proto)| {
+                    .filter(|(_index, obj)| obj.kind.anim().is_some())
+                    .filter(|(_index, obj)| obj.container_uid.is_none())
+                    .filter_map(|(index, obj)| {
+                        library
+                            .with_proto(obj)
+                            .map(|(obj, proto)| (index, obj, proto))
+                    })
+                    .filter(|(_index, _obj, proto)| {
                         (proto.Flags.unwrap_or(0) & fo_defines_fo4rp::fos::ITEM_HIDDEN) == 0
                     })
-                    .map(|(obj, proto)| {
+                    .map(|(index, obj, proto)| {
                         let (hex_x, hex_y) = (obj.map_x.unwrap_or(0), obj.map_y.unwrap_or(0));
-                        let (offset_x, offset_y) = obj.offset();
-                        let (x, y) = (hex_x as i32, hex_y as i32);
-                        let (x, y) = (
-                            /*x = */ y * 16 - x * 24 - (x % 2) * 8 + offset_x,
-                            /*y = */ y * 12 + x * 6 - (x % 2) * 6 + offset_y,
-                        );
-
-                        // TODO: handle flat items and scenery
-                        let z = geometry::draw_order_pos_int(
-                            geometry::DrawOrderType::DRAW_ORDER_SCENERY as u32,
-                            Hex::new(hex_x, hex_y), //TODO: add + proto.DrawOrderOffsetHexY
-                        )
-                        .unwrap_or(0);
+                        let (offset_x, offset_y) = item_offset(obj, proto);
+                        let (x, y) = geometry.position(hex_x, hex_y);
+                        let (x, y) = (x + offset_x, y + offset_y);
 
-                        let asset = assets.upsert_path::<Image>(
-                            &nom_prelude::make_path_conventional(&proto.PicMap),
+                        let draw_order_hex_y = (hex_y as i32
+                            + proto.DrawOrderOffsetHexY.unwrap_or(0) as i32)
+                            .max(0) as u16;
+                        let z = geometry.draw_order(
+                            item_draw_order(proto) as u32,
+                            hex_x,
+                            draw_order_hex_y,
                         );
 
+                        let asset = assets.upsert_path::<Image>(&item_picture(obj, proto));
+
                         Sprite {
                             hex_x,
                             hex_y,
@@ -108,43 +585,96 @@ impl SpriteMap {
                             y,
                             z,
                             asset,
+                            object: Some(index),
+                            layer: item_layer(proto),
                         }
                     })
                     .collect();
+                objects.extend(critters);
+                let hour = Some(map.header.time)
+                    .filter(|time| *time >= 0)
+                    .map(|time| time as f32 / 60.0);
+                let (hex_width, hex_height) = (map.header.max_hex_x, map.header.max_hex_y);
                 let rect = AABB::new();
-                SpriteMap {
+                let map = SpriteMap {
                     rect,
                     tiles,
                     objects,
-                }
+                    roofs,
+                    lights,
+                    infos,
+                    blocks,
+                    markers,
+                    hour,
+                    hex_width,
+                    hex_height,
+                    geometry,
+                };
+                Ok((map, diagnostics))
             },
             Default::default(),
         )
-        .unwrap()
+        .map_err(|err| Error::map_parse(path, err))?
     }
     pub fn sort_sprites(&mut self) {
         self.tiles.sort_by_key(|sprite| sprite.z);
         self.objects.sort_by_key(|sprite| sprite.z);
+        self.roofs.sort_by_key(|sprite| sprite.z);
     }
     fn calc_drawlist(
         &mut self,
         assets: &Assets,
-    ) -> (Vec<SpriteVertex>, Vec<(MaterialId, std::ops::Range<u32>)>) {
+    ) -> (Vec<SpriteVertex>, Vec<DrawCall>, Vec<AnimatedSprite>) {
+        let Self {
+            rect,
+            tiles,
+            objects,
+            roofs,
+            lights,
+            geometry,
+            ..
+        } = self;
+        let light_map = LightMap::new(*geometry, lights);
+        let groups = [&*tiles, &*objects, &*roofs];
         let mut vertices = vec![];
-        let mut materials: Vec<(MaterialId, std::ops::Range<u32>)> = vec![];
+        let mut materials: Vec<DrawCall> = vec![];
+        let mut animations = vec![];
         let mut i = 0u32;
-        for sprite in self.tiles.iter().chain(&self.objects) {
-            if let Some((vertex, material_id)) = calc_sprite(assets, sprite, &mut self.rect) {
-                match materials.last_mut() {
-                    Some((last, range)) if *last == material_id => {
-                        range.end += 1;
+        for sprites in groups.iter() {
+            for sprite in sprites.iter() {
+                let layer = sprite.layer;
+                let light = light_map.get(sprite.hex_x, sprite.hex_y);
+                let placeholder;
+                let sprite = if assets.is_failed(sprite.asset) {
+                    placeholder = placeholder_sprite(*geometry, assets, sprite);
+                    &placeholder
+                } else {
+                    sprite
+                };
+                if let Some((vertex, material_id)) = calc_sprite(assets, sprite, light, rect) {
+                    match materials.last_mut() {
+                        Some(last) if last.layer == layer && last.material_id == material_id => {
+                            last.range.end += 1;
+                        }
+                        _ => {
+                            materials.push(DrawCall {
+                                layer,
+                                material_id,
+                                range: i..i + 1,
+                            });
+                        }
                     }
-                    _ => {
-                        materials.push((material_id, i..i + 1));
+                    if let Some((fps, frames)) = calc_animation(assets, sprite, light) {
+                        animations.push(AnimatedSprite {
+                            index: i,
+                            fps: fps as f32,
+                            frames,
+                            current: 0,
+                        });
                     }
+                    vertices.push(vertex);
+                    i += 1;
                 }
-                vertices.push(vertex);
-                i += 1;
             }
         }
         /*let mut buf = std::collections::BTreeMap::new();
@@ -169,7 +699,55 @@ impl SpriteMap {
             i += 1;
         }
         */
-        (vertices, materials)
+        (vertices, materials, animations)
+    }
+    /// Pixel extent of the hexes between two corners, only the border hexes can reach it.
+    fn hex_rect(&self, from: (u16, u16), to: (u16, u16)) -> AABB {
+        let (x0, x1) = (from.0.min(to.0), from.0.max(to.0));
+        let (y0, y1) = (from.1.min(to.1), from.1.max(to.1));
+        let border = (x0..=x1)
+            .flat_map(|x| vec![(x, y0), (x, y1)])
+            .chain((y0..=y1).flat_map(|y| vec![(x0, y), (x1, y)]));
+        let corners = self.geometry.corners();
+        let mut rect = AABB::new();
+        for (hex_x, hex_y) in border {
+            let (x, y) = self.geometry.position(hex_x, hex_y);
+            for (dx, dy) in corners {
+                rect.insert(x + dx, y + dy);
+            }
+        }
+        rect
+    }
+    fn bounds_rect(&self, bounds: Bounds) -> AABB {
+        match bounds {
+            Bounds::Sprites => self.rect.clone(),
+            Bounds::Header => self.hex_rect(
+                (0, 0),
+                (
+                    self.hex_width.saturating_sub(1),
+                    self.hex_height.saturating_sub(1),
+                ),
+            ),
+            Bounds::Hexes { from, to } => self.hex_rect(from, to),
+            Bounds::Pixels { from, to } => {
+                let mut rect = AABB::new();
+                rect.insert(from.0, from.1);
+                rect.insert(to.0, to.1);
+                rect
+            }
+        }
+    }
+    /// Number of sprites using each asset.
+    pub fn asset_usage(&self) -> HashMap<AssetKey, u32> {
+        let mut usage = HashMap::new();
+        for sprite in self.groups().iter().flat_map(|sprites| sprites.iter()) {
+            *usage.entry(sprite.asset).or_insert(0) += 1;
+        }
+        usage
+    }
+    /// Sprite groups in the order they are drawn: floor tiles, objects, roofs.
+    fn groups(&self) -> [&[Sprite]; 3] {
+        [&self.tiles, &self.objects, &self.roofs]
     }
     pub fn into_renderer(
         self,
@@ -182,9 +760,124 @@ impl SpriteMap {
     }
 }
 
+/// Whether the frame of the sprite shown at `time` has an opaque pixel at the map pixel.
+fn hit_sprite(assets: &Assets, sprite: &Sprite, time: AnimationTime, x: i32, y: i32) -> bool {
+    let mut query = match assets
+        .world
+        .query_one::<(&image::RgbaImage, Option<&ImageOffset>, Option<&Animation>)>(sprite.asset.0)
+    {
+        Ok(query) => query,
+        Err(_) => return false,
+    };
+    let (image, offsets, animation) = match query.get() {
+        Some(found) => found,
+        None => return false,
+    };
+    // `Animation` holds the frames after the first one.
+    let frame = animation.map_or(0, |animation| {
+        animation_frame(time, animation.fps as f32, animation.frames.len() + 1)
+    });
+    let (image, offsets) = match (frame, animation) {
+        (0, _) | (_, None) => (image, offsets.copied().unwrap_or_default()),
+        (frame, Some(animation)) => {
+            let frame = &animation.frames[frame - 1];
+            let offsets = ImageOffset {
+                x: frame.offset_x,
+                y: frame.offset_y,
+            };
+            (&frame.image, offsets)
+        }
+    };
+    let px = x - (sprite.x + offsets.x as i32);
+    let py = y - (sprite.y + offsets.y as i32);
+    if px < 0 || py < 0 || px >= image.width() as i32 || py >= image.height() as i32 {
+        return false;
+    }
+    image.get_pixel(px as u32, py as u32)[3] > 0
+}
+
+/// `PicMapName` of the object, if it is set.
+fn pic_map_override(obj: &fo_map_format::Object) -> Option<String> {
+    obj.pic_map_name
+        .as_ref()
+        .map(|name| name.to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Conventional path of the map picture: the object's override, the proto's `PicMap` otherwise.
+fn item_picture(obj: &fo_map_format::Object, proto: &fo_proto_format::ProtoItem) -> String {
+    match pic_map_override(obj) {
+        Some(name) => nom_prelude::make_path_conventional(&name),
+        None => nom_prelude::make_path_conventional(&proto.PicMap),
+    }
+}
+
+/// Sprite offset of an item, per axis the object's own offset wins over the proto's,
+/// as `ItemHex` does in the engine.
+fn item_offset(obj: &fo_map_format::Object, proto: &fo_proto_format::ProtoItem) -> (i32, i32) {
+    use fo_map_format::Offset;
+
+    let (x, y) = obj.offset();
+    let or_proto = |own: i32, proto: Option<i16>| {
+        if own != 0 {
+            own
+        } else {
+            proto.unwrap_or(0) as i32
+        }
+    };
+    (or_proto(x, proto.OffsetX), or_proto(y, proto.OffsetY))
+}
+
+/// Walls, then scenery the same way the engine sorts it, everything else is an item.
+fn item_layer(proto: &fo_proto_format::ProtoItem) -> Layer {
+    use fo_defines_fo4rp::fos;
+
+    let item_type = proto.Type as u32;
+    if item_type == fos::ITEM_TYPE_WALL as u32 {
+        Layer::Walls
+    } else if item_type == fos::ITEM_TYPE_GENERIC as u32 || item_type == fos::ITEM_TYPE_GRID as u32
+    {
+        Layer::Scenery
+    } else {
+        Layer::Items
+    }
+}
+
+/// Draw order class of an item, same as `ItemHex` picks it in the engine.
+/// Walls share the scenery class, flat sprites go below critters.
+fn item_draw_order(proto: &fo_proto_format::ProtoItem) -> draw_geometry::fo::DrawOrderType {
+    use draw_geometry::fo::DrawOrderType;
+    use fo_defines_fo4rp::fos;
+
+    let flat = (proto.Flags.unwrap_or(0) & fos::ITEM_FLAT) != 0;
+    let scenery = matches!(item_layer(proto), Layer::Walls | Layer::Scenery);
+    match (flat, scenery) {
+        (true, true) => DrawOrderType::DRAW_ORDER_FLAT_SCENERY,
+        (true, false) => DrawOrderType::DRAW_ORDER_FLAT_ITEM,
+        (false, true) => DrawOrderType::DRAW_ORDER_SCENERY,
+        (false, false) => DrawOrderType::DRAW_ORDER_ITEM,
+    }
+}
+
+/// Sprite whose vertex is rewritten as its animation plays.
+struct AnimatedSprite {
+    index: u32,
+    fps: f32,
+    frames: Vec<SpriteVertex>,
+    current: usize,
+}
+
+#[derive(Debug)]
+struct DrawCall {
+    layer: Layer,
+    material_id: MaterialId,
+    range: std::ops::Range<u32>,
+}
+
 fn calc_sprite(
     assets: &Assets,
     sprite: &Sprite,
+    light: [u8; 4],
     rect: &mut AABB,
 ) -> Option<(SpriteVertex, MaterialId)> {
     let mut query = assets
@@ -192,31 +885,86 @@ fn calc_sprite(
         .query_one::<(&TextureView, &ImageSize, Option<&ImageOffset>)>(sprite.asset.0)
         .ok()?;
     let (view, size, offsets) = query.get()?;
-    let width = size.0.width as i32;
-    let height = size.0.height as i32;
     let offsets = offsets.copied().unwrap_or_default();
-    let x0 = sprite.x + offsets.x as i32;
-    let y0 = sprite.y + offsets.y as i32;
-    let x1 = x0 + width;
-    let y1 = y0 + height;
+    let size = (size.0.width as u32, size.0.height as u32);
+    let (x0, y0, x1, y1) = sprite_rect(sprite, size, offsets);
     rect.insert_rect(x0, y0, x1, y1);
 
     Some((
-        SpriteVertex {
-            pos: [x0 as f32, y0 as f32],
-            size: [width as f32, height as f32],
-            tex: [
-                view.rect.min.x,
-                view.rect.min.y,
-                view.rect.max.x,
-                view.rect.max.y,
-            ],
-        },
+        sprite_vertex(sprite, view, offsets, light),
         view.material_id,
     ))
 }
 
-fn shader_module_from_file(device: &wgpu::Device, path: &Path) -> wgpu::ShaderModule {
+/// Placeholder drawn for a sprite whose asset failed to load, centered on its hex.
+fn placeholder_sprite(geometry: Geometry, assets: &Assets, sprite: &Sprite) -> Sprite {
+    let (x, y) = geometry.position(sprite.hex_x, sprite.hex_y);
+    Sprite {
+        x,
+        y,
+        asset: assets.placeholder(),
+        ..sprite.clone()
+    }
+}
+
+/// Map pixels covered by a frame of the sprite: left, top, right, bottom.
+fn sprite_rect(sprite: &Sprite, size: (u32, u32), offsets: ImageOffset) -> (i32, i32, i32, i32) {
+    let x0 = sprite.x + offsets.x as i32;
+    let y0 = sprite.y + offsets.y as i32;
+    (x0, y0, x0 + size.0 as i32, y0 + size.1 as i32)
+}
+
+/// Frame of an animation with `len` frames shown at the moment.
+fn animation_frame(time: AnimationTime, fps: f32, len: usize) -> usize {
+    let frame = match time {
+        AnimationTime::Frame(frame) => frame,
+        AnimationTime::Seconds(seconds) => (seconds * fps) as usize,
+    };
+    frame % len
+}
+
+/// Vertices for every frame of an animated sprite, all frames share the first frame's material.
+fn calc_animation(
+    assets: &Assets,
+    sprite: &Sprite,
+    light: [u8; 4],
+) -> Option<(u16, Vec<SpriteVertex>)> {
+    let views = assets.world.get::<AnimationViews>(sprite.asset.0).ok()?;
+    let frames = views
+        .frames
+        .iter()
+        .map(|(view, offsets)| sprite_vertex(sprite, view, *offsets, light))
+        .collect();
+    Some((views.fps, frames))
+}
+
+fn sprite_vertex(
+    sprite: &Sprite,
+    view: &TextureView,
+    offsets: ImageOffset,
+    light: [u8; 4],
+) -> SpriteVertex {
+    let x0 = sprite.x + offsets.x as i32;
+    let y0 = sprite.y + offsets.y as i32;
+    SpriteVertex {
+        pos: [x0 as f32, y0 as f32],
+        size: [view.rect.width() as f32, view.rect.height() as f32],
+        tex: [
+            view.rect.min.x,
+            view.rect.min.y,
+            view.rect.max.x,
+            view.rect.max.y,
+        ],
+        light,
+    }
+}
+
+/// Projects the map pixel rectangle onto the whole render target.
+fn ortho(left: f32, top: f32, width: f32, height: f32) -> euclid::default::Transform3D<f32> {
+    euclid::default::Transform3D::ortho(left, left + width, top + height, top, -1.0, 1.0)
+}
+
+pub(crate) fn shader_module_from_file(device: &wgpu::Device, path: &Path) -> wgpu::ShaderModule {
     let file = std::fs::read(path).unwrap();
     let source = wgpu::util::make_spirv(&file);
     device.create_shader_module(&wgpu::ShaderModuleDescriptor {
@@ -300,11 +1048,12 @@ fn sprite_pipeline(
 }
 
 #[repr(C)]
-#[derive(AsBytes)]
+#[derive(AsBytes, Clone, Copy)]
 struct SpriteVertex {
     pos: [f32; 2],
     size: [f32; 2],
     tex: [u16; 4],
+    light: [u8; 4],
 }
 
 impl SpriteVertex {
@@ -328,12 +1077,18 @@ impl SpriteVertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Ushort4,
                 },
+                wgpu::VertexAttribute {
+                    offset: (std::mem::size_of::<[f32; 4]>() + std::mem::size_of::<[u16; 4]>())
+                        as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Uchar4Norm,
+                },
             ],
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct AABB {
     top_left: (i32, i32),
     bottom_right: (i32, i32),
@@ -361,7 +1116,7 @@ impl AABB {
             .try_into()
             .ok()
     }
-    fn _insert(&mut self, x: i32, y: i32) {
+    fn insert(&mut self, x: i32, y: i32) {
         if x < self.top_left.0 {
             self.top_left.0 = x;
         }
@@ -375,6 +1130,12 @@ impl AABB {
             self.bottom_right.1 = y;
         }
     }
+    fn padded(&self, padding: i32) -> Self {
+        Self {
+            top_left: (self.top_left.0 - padding, self.top_left.1 - padding),
+            bottom_right: (self.bottom_right.0 + padding, self.bottom_right.1 + padding),
+        }
+    }
     fn insert_rect(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
         if x0 < self.top_left.0 {
             self.top_left.0 = x0;
@@ -391,9 +1152,68 @@ impl AABB {
     }
 }
 
+/// Side of the tiles written by `SpriteMapRenderer::render_pyramid`.
+pub const PYRAMID_TILE_SIZE: u32 = 256;
+
+/// Description of a tile pyramid, written next to the tiles as `manifest.json`.
+///
+/// Zoom `max_zoom` is one image pixel per map pixel, every lower zoom halves the scale.
+/// Tile `z/x/y.png` covers `tile_size` image pixels starting at `(x, y) * tile_size`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PyramidManifest {
+    pub tile_size: u32,
+    pub min_zoom: u32,
+    pub max_zoom: u32,
+    /// Map pixel at the top left corner of every zoom level.
+    pub origin: (i32, i32),
+    /// Bounds in map pixels: left, top, right, bottom.
+    pub bounds: [i32; 4],
+    /// Image size at `max_zoom`.
+    pub width: u32,
+    pub height: u32,
+    pub geometry: Geometry,
+    /// Map size in hexes, from the header.
+    pub hex_width: u16,
+    pub hex_height: u16,
+}
+
+/// Where the output image lies on the map.
+#[derive(Debug, Copy, Clone)]
+pub struct OutputView {
+    /// Map pixel at the top left corner of the image.
+    pub left: f32,
+    pub top: f32,
+    /// Image pixels per map pixel.
+    pub scale: f32,
+    pub width: u32,
+    pub height: u32,
+}
+
 pub struct SpriteMapRenderer {
     map: SpriteMap,
-    drawlist: Vec<(MaterialId, std::ops::Range<u32>)>,
+    drawlist: Vec<DrawCall>,
+    animations: Vec<AnimatedSprite>,
+    /// Moment shown by `animations`.
+    animation: AnimationTime,
+    layers: Layers,
+    lighting: bool,
+    ambient: f32,
+    hour: Option<f32>,
+    /// Built on the first `set_grid(true)`, most renders never show it.
+    grid: Option<Overlay>,
+    show_grid: bool,
+    grid_color: [u8; 4],
+    format: wgpu::TextureFormat,
+    shaders: String,
+    passability: Overlay,
+    show_passability: bool,
+    marker_fill: Overlay,
+    marker_lines: Overlay,
+    show_markers: bool,
+    /// Output area in map pixels, see `Bounds`.
+    rect: AABB,
+    scale: Option<f32>,
+    output_size: Option<(u32, u32)>,
     pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     uniform_buffer: wgpu::Buffer,
@@ -409,14 +1229,14 @@ impl SpriteMapRenderer {
         format: wgpu::TextureFormat,
         config: &Config,
     ) -> Self {
-        let (vertices, materials) = map.calc_drawlist(assets);
+        let (vertices, materials, animations) = map.calc_drawlist(assets);
         use wgpu::util::DeviceExt;
         let vertex_buffer = wgpu
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
                 contents: vertices.as_bytes(),
-                usage: wgpu::BufferUsage::VERTEX,
+                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
             });
 
         /*
@@ -454,9 +1274,53 @@ impl SpriteMapRenderer {
         };
 
         let pipeline = sprite_pipeline(&wgpu, format, config.paths.shaders.as_ref());
+        let passability = Overlay::new(
+            wgpu,
+            format,
+            config.paths.shaders.as_ref(),
+            wgpu::PrimitiveTopology::TriangleList,
+            &hex_fill(
+                map.geometry,
+                map.blocks.iter().map(|(hex, block)| (*hex, block.color())),
+            ),
+        );
+        let (marker_fill, marker_lines) = markers::glyphs(map.geometry, &map.markers);
+        let marker_fill = Overlay::new(
+            wgpu,
+            format,
+            config.paths.shaders.as_ref(),
+            wgpu::PrimitiveTopology::TriangleList,
+            &marker_fill,
+        );
+        let marker_lines = Overlay::new(
+            wgpu,
+            format,
+            config.paths.shaders.as_ref(),
+            wgpu::PrimitiveTopology::LineList,
+            &marker_lines,
+        );
         Self {
+            rect: map.rect.clone(),
+            scale: None,
+            output_size: None,
             map,
             drawlist: materials,
+            animations,
+            animation: AnimationTime::default(),
+            layers: Layers::all(),
+            lighting: true,
+            ambient: 1.0,
+            hour: None,
+            grid: None,
+            show_grid: false,
+            grid_color: config.grid.rgba(),
+            format,
+            shaders: config.paths.shaders.clone(),
+            passability,
+            show_passability: false,
+            marker_fill,
+            marker_lines,
+            show_markers: false,
             pipeline,
             vertex_buffer,
             uniform_buffer,
@@ -464,14 +1328,157 @@ impl SpriteMapRenderer {
             background,
         }
     }
-    pub fn render_into_texture(&self, wgpu: &Wgpu) -> SizedBuffer {
-        let rect = &self.map.rect;
-        let dimensions = (rect.width().unwrap(), rect.height().unwrap());
-        //let dimensions = (1920, 1080);
-
+    pub fn layers(&self) -> Layers {
+        self.layers
+    }
+    pub fn set_layers(&mut self, layers: Layers) {
+        self.layers = layers;
+    }
+    pub fn toggle_layer(&mut self, layer: Layer) {
+        self.layers.toggle(layer);
+    }
+    pub fn lighting(&self) -> bool {
+        self.lighting
+    }
+    pub fn set_lighting(&mut self, lighting: bool, ambient: f32) {
+        self.lighting = lighting;
+        self.ambient = ambient;
+    }
+    pub fn grid(&self) -> bool {
+        self.show_grid
+    }
+    pub fn set_grid(&mut self, wgpu: &Wgpu, grid: bool) {
+        self.show_grid = grid;
+        if grid && self.grid.is_none() {
+            self.grid = Some(Overlay::new(
+                wgpu,
+                self.format,
+                self.shaders.as_ref(),
+                wgpu::PrimitiveTopology::LineList,
+                &hex_grid(
+                    self.map.geometry,
+                    self.map.hex_width,
+                    self.map.hex_height,
+                    self.grid_color,
+                ),
+            ));
+        }
+    }
+    pub fn passability(&self) -> bool {
+        self.show_passability
+    }
+    pub fn set_passability(&mut self, passability: bool) {
+        self.show_passability = passability;
+    }
+    pub fn markers(&self) -> bool {
+        self.show_markers
+    }
+    pub fn set_markers(&mut self, markers: bool) {
+        self.show_markers = markers;
+    }
+    /// Sets the output area, `padding` pixels are added on every side.
+    /// Fails if the area is empty, as the sprites of a map without any are.
+    pub fn set_bounds(&mut self, bounds: Bounds, padding: u32) -> Result<()> {
+        let rect = self.map.bounds_rect(bounds);
+        if rect.width().is_none() || rect.height().is_none() {
+            return Err(Error::NothingToDraw);
+        }
+        self.rect = rect.padded(padding as i32);
+        Ok(())
+    }
+    /// Size of the output area in map pixels, `set_bounds` keeps it from being empty.
+    fn rect_size(&self) -> (u32, u32) {
+        (
+            self.rect.width().unwrap_or_default(),
+            self.rect.height().unwrap_or_default(),
+        )
+    }
+    /// Sets the zoom and size of rendered images, see `RenderOptions::scale` and `size`.
+    pub fn set_output(&mut self, scale: Option<f32>, size: Option<(u32, u32)>) {
+        self.scale = scale;
+        self.output_size = size;
+    }
+    /// Placement of the bounds in the rendered image.
+    pub fn output_view(&self) -> OutputView {
+        let (width, height) = self.rect_size();
+        let (width, height) = (width as f32, height as f32);
+        match (self.scale, self.output_size) {
+            (scale, None) => {
+                let scale = scale.unwrap_or(1.0);
+                OutputView {
+                    left: self.rect.top_left.0 as f32,
+                    top: self.rect.top_left.1 as f32,
+                    scale,
+                    width: ((width * scale).round() as u32).max(1),
+                    height: ((height * scale).round() as u32).max(1),
+                }
+            }
+            (scale, Some((output_width, output_height))) => {
+                let scale = scale.unwrap_or_else(|| {
+                    (output_width as f32 / width).min(output_height as f32 / height)
+                });
+                let center_x = self.rect.top_left.0 as f32 + width / 2.0;
+                let center_y = self.rect.top_left.1 as f32 + height / 2.0;
+                OutputView {
+                    left: center_x - output_width as f32 / scale / 2.0,
+                    top: center_y - output_height as f32 / scale / 2.0,
+                    scale,
+                    width: output_width.max(1),
+                    height: output_height.max(1),
+                }
+            }
+        }
+    }
+    /// Hour used for the day/night tint, if any.
+    pub fn hour(&self) -> Option<f32> {
+        self.hour.or(self.map.hour)
+    }
+    /// Overrides the time of day, `None` falls back to the map header time.
+    pub fn set_hour(&mut self, hour: Option<f32>) {
+        self.hour = hour;
+    }
+    fn uniforms(&self, matrix: euclid::default::Transform3D<f32>) -> SpriteUniforms {
+        // Full ambient saturates every sprite, which is the same as no lighting at all.
+        let ambient = if self.lighting {
+            let [r, g, b] = self.hour().map(day_color).unwrap_or([1.0; 3]);
+            [r * self.ambient, g * self.ambient, b * self.ambient, 1.0]
+        } else {
+            [1.0; 4]
+        };
+        SpriteUniforms {
+            projection_matrix: matrix.to_array(),
+            ambient,
+        }
+    }
+    pub fn set_animation_time(&mut self, wgpu: &Wgpu, time: AnimationTime) {
+        self.animation = time;
+        for animated in &mut self.animations {
+            let frame = animation_frame(time, animated.fps, animated.frames.len());
+            if frame != animated.current {
+                animated.current = frame;
+                let offset = animated.index as wgpu::BufferAddress
+                    * std::mem::size_of::<SpriteVertex>() as wgpu::BufferAddress;
+                wgpu.queue.write_buffer(
+                    &self.vertex_buffer,
+                    offset,
+                    animated.frames[frame].as_bytes(),
+                );
+            }
+        }
+    }
+    /// Renders a part of the output, `x` and `y` are pixels from the top left of the image.
+    fn render_tile(
+        &self,
+        wgpu: &Wgpu,
+        output: &OutputView,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> SizedBuffer {
         let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
+            width,
+            height,
             depth: 1,
         };
 
@@ -480,29 +1487,138 @@ impl SpriteMapRenderer {
             .then_translate(vec2(-self.rect.top_left.0 as f32, -self.rect.top_left.1 as f32))
             .then_scale(1.0 / size.width as f32, 1.0 / size.height as f32)
             .to_3d()*/
-            euclid::default::Transform3D::ortho(
-                rect.top_left.0 as f32,
-                rect.bottom_right.0 as f32,
-                rect.bottom_right.1 as f32,
-                rect.top_left.1 as f32,
-                -1.0,
-                1.0,
+            ortho(
+                output.left + x as f32 / output.scale,
+                output.top + y as f32 / output.scale,
+                width as f32 / output.scale,
+                height as f32 / output.scale,
             )
         };
 
-        let uniforms = SpriteUniforms {
-            projection_matrix: matrix.to_array(),
-        };
+        let uniforms = self.uniforms(matrix);
 
         let sized_texture = SizedTexture::new(&wgpu.device, size);
         self.render(wgpu, &sized_texture.view(), uniforms);
         sized_texture.save_to_buffer(wgpu)
     }
 
+    /// Renders the output in tiles of at most `tile_size` pixels and streams them into a PNG,
+    /// one band of tiles at a time, so the output size is not limited by the GPU texture size.
+    pub async fn render_to_png(&self, wgpu: &Wgpu, path: &str, tile_size: u32) -> Result<()> {
+        use std::io::Write;
+
+        let output = self.output_view();
+        let (width, height) = (output.width, output.height);
+        let tile_size = tile_size.max(1);
+
+        let file = std::fs::File::create(path).map_err(|err| Error::output(path, err))?;
+        let mut png_encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+        png_encoder.set_depth(png::BitDepth::Eight);
+        png_encoder.set_color(png::ColorType::RGBA);
+        let mut png_writer = png_encoder
+            .write_header()
+            .map_err(|err| Error::output(path, err))?
+            .into_stream_writer_with_size(width as usize * 4);
+
+        for band_y in (0..height).step_by(tile_size as usize) {
+            let band_height = tile_size.min(height - band_y);
+            let mut tiles = vec![];
+            for tile_x in (0..width).step_by(tile_size as usize) {
+                let tile_width = tile_size.min(width - tile_x);
+                let buffer =
+                    self.render_tile(wgpu, &output, tile_x, band_y, tile_width, band_height);
+                let rows = buffer
+                    .read_rows(&wgpu.device)
+                    .await
+                    .ok_or_else(|| Error::output(path, "rendered tile can't be read back"))?;
+                tiles.push((buffer.width() as usize * 4, rows));
+            }
+            for row in 0..band_height as usize {
+                for (stride, rows) in &tiles {
+                    png_writer
+                        .write_all(&rows[row * stride..][..*stride])
+                        .map_err(|err| Error::output(path, err))?;
+                }
+            }
+        }
+        png_writer
+            .finish()
+            .map_err(|err| Error::output(path, err))?;
+        Ok(())
+    }
+
+    /// Renders `z/x/y.png` tiles of `PYRAMID_TILE_SIZE` pixels into `dir`, from one tile
+    /// for the whole bounds at zoom 0 up to one image pixel per map pixel.
+    pub async fn render_pyramid(&self, wgpu: &Wgpu, dir: &Path) -> Result<PyramidManifest> {
+        let (width, height) = self.rect_size();
+        let (width, height) = (width.max(1), height.max(1));
+        let mut max_zoom = 0;
+        while (PYRAMID_TILE_SIZE << max_zoom) < width.max(height) {
+            max_zoom += 1;
+        }
+
+        for zoom in 0..=max_zoom {
+            let shift = max_zoom - zoom;
+            let output = OutputView {
+                left: self.rect.top_left.0 as f32,
+                top: self.rect.top_left.1 as f32,
+                scale: 1.0 / (1 << shift) as f32,
+                width: (width + (1 << shift) - 1) >> shift,
+                height: (height + (1 << shift) - 1) >> shift,
+            };
+            let columns = (output.width + PYRAMID_TILE_SIZE - 1) / PYRAMID_TILE_SIZE;
+            let rows = (output.height + PYRAMID_TILE_SIZE - 1) / PYRAMID_TILE_SIZE;
+            for x in 0..columns {
+                let column_dir = dir.join(zoom.to_string()).join(x.to_string());
+                std::fs::create_dir_all(&column_dir)
+                    .map_err(|err| Error::output(column_dir.display(), err))?;
+                for y in 0..rows {
+                    let path = column_dir.join(format!("{}.png", y));
+                    let buffer = self.render_tile(
+                        wgpu,
+                        &output,
+                        x * PYRAMID_TILE_SIZE,
+                        y * PYRAMID_TILE_SIZE,
+                        PYRAMID_TILE_SIZE,
+                        PYRAMID_TILE_SIZE,
+                    );
+                    let pixels = buffer.read_rows(&wgpu.device).await.ok_or_else(|| {
+                        Error::output(path.display(), "rendered tile can't be read back")
+                    })?;
+                    image::save_buffer(
+                        &path,
+                        &pixels,
+                        PYRAMID_TILE_SIZE,
+                        PYRAMID_TILE_SIZE,
+                        image::ColorType::Rgba8,
+                    )
+                    .map_err(|err| Error::output(path.display(), err))?;
+                }
+            }
+        }
+
+        Ok(PyramidManifest {
+            tile_size: PYRAMID_TILE_SIZE,
+            min_zoom: 0,
+            max_zoom,
+            origin: self.rect.top_left,
+            bounds: [
+                self.rect.top_left.0,
+                self.rect.top_left.1,
+                self.rect.bottom_right.0,
+                self.rect.bottom_right.1,
+            ],
+            width,
+            height,
+            geometry: self.map.geometry,
+            hex_width: self.map.hex_width,
+            hex_height: self.map.hex_height,
+        })
+    }
+
     fn xy_ratios(&self, width: u32, height: u32) -> (f32, f32) {
-        let rect = &self.map.rect;
-        let map_width = rect.width().unwrap() as f32;
-        let map_height = rect.height().unwrap() as f32;
+        let (map_width, map_height) = self.rect_size();
+        let (map_width, map_height) = (map_width as f32, map_height as f32);
         let window_width = width as f32;
         let window_height = height as f32;
 
@@ -517,6 +1633,26 @@ impl SpriteMapRenderer {
         1.0 / x_ratio.max(y_ratio)
     }
 
+    fn view_matrix(
+        &self,
+        width: u32,
+        height: u32,
+        zoom: f32,
+        shift_x: f32,
+        shift_y: f32,
+    ) -> euclid::default::Transform3D<f32> {
+        let (x_ratio, y_ratio) = self.xy_ratios(width, height);
+        let (map_width, map_height) = self.rect_size();
+        ortho(
+            self.rect.top_left.0 as f32,
+            self.rect.top_left.1 as f32,
+            map_width as f32,
+            map_height as f32,
+        )
+        .then_translate(euclid::vec3(shift_x, shift_y, 0.0))
+        .then_scale(x_ratio * zoom, y_ratio * zoom, 1.0)
+    }
+
     pub fn render_view(
         &self,
         wgpu: &Wgpu,
@@ -527,25 +1663,142 @@ impl SpriteMapRenderer {
         shift_x: f32,
         shift_y: f32,
     ) {
-        let (x_ratio, y_ratio) = self.xy_ratios(width, height);
-        let matrix = {
-            let rect = &self.map.rect;
-            euclid::default::Transform3D::ortho(
-                rect.top_left.0 as f32,
-                rect.bottom_right.0 as f32,
-                rect.bottom_right.1 as f32,
-                rect.top_left.1 as f32,
-                -1.0,
-                1.0,
-            )
-            .then_translate(euclid::vec3(shift_x, shift_y, 0.0))
-            .then_scale(x_ratio * zoom, y_ratio * zoom, 1.0)
-        };
-        let uniforms = SpriteUniforms {
-            projection_matrix: matrix.to_array(),
-        };
+        let matrix = self.view_matrix(width, height, zoom, shift_x, shift_y);
+        let uniforms = self.uniforms(matrix);
         self.render(wgpu, view, uniforms);
     }
+
+    /// Inverse of the `render_view` projection: window pixel to map pixel.
+    #[allow(clippy::too_many_arguments)]
+    pub fn screen_to_map(
+        &self,
+        width: u32,
+        height: u32,
+        zoom: f32,
+        shift_x: f32,
+        shift_y: f32,
+        cursor_x: f32,
+        cursor_y: f32,
+    ) -> Option<(f32, f32)> {
+        let ndc = euclid::point2(
+            cursor_x / width as f32 * 2.0 - 1.0,
+            1.0 - cursor_y / height as f32 * 2.0,
+        );
+        let point = self
+            .view_matrix(width, height, zoom, shift_x, shift_y)
+            .inverse()?
+            .transform_point2d(ndc)?;
+        Some((point.x, point.y))
+    }
+
+    pub fn object(&self, index: usize) -> Option<&ObjectInfo> {
+        self.map.infos.get(index)
+    }
+    /// Items placed inside the object, matched by container UID.
+    pub fn children<'a>(&'a self, object: &ObjectInfo) -> impl Iterator<Item = &'a ObjectInfo> {
+        let uid = object.uid;
+        self.map
+            .infos
+            .iter()
+            .filter(move |child| uid.is_some() && child.container_uid == uid)
+    }
+    /// Hex of the map under the map pixel.
+    pub fn pick_hex(&self, x: f32, y: f32) -> Option<(u16, u16)> {
+        self.map
+            .geometry
+            .pixel_hex(x, y)
+            .filter(|(hex_x, hex_y)| *hex_x < self.map.hex_width && *hex_y < self.map.hex_height)
+    }
+
+    /// Topmost visible sprite with an opaque pixel at the map pixel.
+    pub fn pick_sprite(&self, assets: &Assets, x: f32, y: f32) -> Option<PickedSprite> {
+        let (x, y) = (x.floor() as i32, y.floor() as i32);
+        self.map.groups().iter().rev().find_map(|sprites| {
+            let index = sprites.iter().rposition(|sprite| {
+                if !self.layers.contains(sprite.layer) {
+                    return false;
+                }
+                if assets.is_failed(sprite.asset) {
+                    let placeholder = placeholder_sprite(self.map.geometry, assets, sprite);
+                    hit_sprite(assets, &placeholder, self.animation, x, y)
+                } else {
+                    hit_sprite(assets, sprite, self.animation, x, y)
+                }
+            })?;
+            let sprite = &sprites[index];
+            Some(PickedSprite {
+                layer: sprite.layer,
+                index,
+                hex_x: sprite.hex_x,
+                hex_y: sprite.hex_y,
+                path: assets.path(sprite.asset),
+                object: sprite.object,
+            })
+        })
+    }
+    /// Map pixels covered by the frame of the sprite shown at the current animation time.
+    fn frame_rect(&self, assets: &Assets, sprite: &Sprite) -> Option<(i32, i32, i32, i32)> {
+        if let Ok(views) = assets.world.get::<AnimationViews>(sprite.asset.0) {
+            let frame = animation_frame(self.animation, views.fps as f32, views.frames.len());
+            let (view, offsets) = &views.frames[frame];
+            let size = (view.rect.width() as u32, view.rect.height() as u32);
+            return Some(sprite_rect(sprite, size, *offsets));
+        }
+        let mut query = assets
+            .world
+            .query_one::<(&ImageSize, Option<&ImageOffset>)>(sprite.asset.0)
+            .ok()?;
+        let (size, offsets) = query.get()?;
+        let size = (size.0.width as u32, size.0.height as u32);
+        Some(sprite_rect(
+            sprite,
+            size,
+            offsets.copied().unwrap_or_default(),
+        ))
+    }
+    /// Sprites of the visible layers that overlap the output image, in draw order.
+    pub fn placements(&self, assets: &Assets) -> Vec<SpritePlacement> {
+        let output = self.output_view();
+        let mut placements = vec![];
+        for sprites in self.map.groups().iter() {
+            for sprite in sprites.iter() {
+                if !self.layers.contains(sprite.layer) {
+                    continue;
+                }
+                let placeholder;
+                let drawn = if assets.is_failed(sprite.asset) {
+                    placeholder = placeholder_sprite(self.map.geometry, assets, sprite);
+                    &placeholder
+                } else {
+                    sprite
+                };
+                let (x0, y0, x1, y1) = match self.frame_rect(assets, drawn) {
+                    Some(rect) => rect,
+                    None => continue,
+                };
+                let to_output = |x: i32, origin: f32| ((x as f32 - origin) * output.scale).round();
+                let (x0, x1) = (to_output(x0, output.left), to_output(x1, output.left));
+                let (y0, y1) = (to_output(y0, output.top), to_output(y1, output.top));
+                if x1 <= 0.0 || y1 <= 0.0 || x0 >= output.width as f32 || y0 >= output.height as f32
+                {
+                    continue;
+                }
+                placements.push(SpritePlacement {
+                    layer: sprite.layer,
+                    x: x0 as i32,
+                    y: y0 as i32,
+                    width: (x1 - x0) as u32,
+                    height: (y1 - y0) as u32,
+                    hex_x: sprite.hex_x,
+                    hex_y: sprite.hex_y,
+                    proto_id: sprite.object.map(|index| self.map.infos[index].proto_id),
+                    object: sprite.object,
+                    path: assets.path(sprite.asset),
+                });
+            }
+        }
+        placements
+    }
     fn render(&self, wgpu: &Wgpu, view: &wgpu::TextureView, uniforms: SpriteUniforms) {
         //dbg!(self.drawlist.len());
         //let before = std::time::Instant::now();
@@ -569,15 +1822,27 @@ impl SpriteMapRenderer {
                 }],
                 depth_stencil_attachment: None,
             });
-            rpass.set_pipeline(&self.pipeline);
-            rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             rpass.set_bind_group(0, &self.uniform_bind_group, &[]);
 
-            //for (key, group) in &materials.into_iter().zip(0u32..).group_by(|(material_id, _)| material_id) {
-            for (material_id, range) in self.drawlist.iter() {
-                let texture = wgpu.material(*material_id);
-                rpass.set_bind_group(1, &texture.bind_group, &[]);
-                rpass.draw(0..6, range.clone());
+            // Overlays go on top of floor tiles, but below everything standing on them.
+            let (floor, rest): (Vec<_>, Vec<_>) = self
+                .drawlist
+                .iter()
+                .filter(|call| self.layers.contains(call.layer))
+                .partition(|call| call.layer == Layer::Tiles);
+            let overlays = self.layers.contains(Layer::Overlays);
+            self.draw_sprites(wgpu, &mut rpass, &floor);
+            if let Some(grid) = self.grid.as_ref().filter(|_| overlays && self.show_grid) {
+                grid.draw(&mut rpass);
+            }
+            self.draw_sprites(wgpu, &mut rpass, &rest);
+            // Blockers are mostly walls, so they have to stay visible above them.
+            if overlays && self.show_passability {
+                self.passability.draw(&mut rpass);
+            }
+            if overlays && self.show_markers {
+                self.marker_fill.draw(&mut rpass);
+                self.marker_lines.draw(&mut rpass);
             }
         }
 
@@ -587,4 +1852,20 @@ impl SpriteMapRenderer {
 
         //println!("Render completed in {} us", before.elapsed().as_micros());
     }
+    fn draw_sprites<'a>(
+        &'a self,
+        wgpu: &'a Wgpu,
+        rpass: &mut wgpu::RenderPass<'a>,
+        drawlist: &[&DrawCall],
+    ) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        //for (key, group) in &materials.into_iter().zip(0u32..).group_by(|(material_id, _)| material_id) {
+        for call in drawlist {
+            let texture = wgpu.material(call.material_id);
+            rpass.set_bind_group(1, &texture.bind_group, &[]);
+            rpass.draw(0..6, call.range.clone());
+        }
+    }
 }
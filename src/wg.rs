@@ -219,6 +219,7 @@ impl Wgpu {
 #[derive(AsBytes)]
 pub struct SpriteUniforms {
     pub projection_matrix: [f32; 16],
+    pub ambient: [f32; 4],
 }
 
 #[derive(Debug)]
@@ -325,50 +326,23 @@ impl SizedBuffer {
             buffer,
         }
     }
-    pub async fn save_to_png(&self, device: &wgpu::Device, path: &str) {
-        use std::io::Write;
-
-        // Note that we're not calling `.await` here.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    /// Pixel rows without the copy alignment padding, `None` if the buffer can't be mapped.
+    pub async fn read_rows(&self, device: &wgpu::Device) -> Option<Vec<u8>> {
         let buffer_slice = self.buffer.slice(..);
         let buffer_future = buffer_slice.map_async(wgpu::MapMode::Read);
-
-        // Poll the device in a blocking manner so that our future resolves.
-        // In an actual application, `device.poll(...)` should
-        // be called in an event loop or on another thread.
         device.poll(wgpu::Maintain::Wait);
-        // If a file system is available, write the buffer as a PNG
-        let has_file_system_available = cfg!(not(target_arch = "wasm32"));
-        if !has_file_system_available {
-            return;
-        }
-
-        if let Ok(()) = buffer_future.await {
-            let padded_buffer = buffer_slice.get_mapped_range();
-
-            let file = std::fs::File::create(path).unwrap();
-            let writer = std::io::BufWriter::new(file);
-
-            let mut png_encoder = png::Encoder::new(writer, self.width, self.height);
-            png_encoder.set_depth(png::BitDepth::Eight);
-            png_encoder.set_color(png::ColorType::RGBA);
-            let mut png_writer = png_encoder
-                .write_header()
-                .unwrap()
-                .into_stream_writer_with_size(self.unpadded_bytes_per_row as usize);
-
-            // from the padded_buffer we write just the unpadded bytes into the image
-            for chunk in padded_buffer.chunks(self.padded_bytes_per_row as usize) {
-                png_writer
-                    .write(&chunk[..self.unpadded_bytes_per_row as usize])
-                    .unwrap();
-            }
-            png_writer.finish().unwrap();
-
-            // With the current interface, we have to make sure all mapped views are
-            // dropped before we unmap the buffer.
-            drop(padded_buffer);
+        buffer_future.await.ok()?;
 
-            self.buffer.unmap();
+        let padded_buffer = buffer_slice.get_mapped_range();
+        let mut rows = Vec::with_capacity((self.unpadded_bytes_per_row * self.height) as usize);
+        for chunk in padded_buffer.chunks(self.padded_bytes_per_row as usize) {
+            rows.extend_from_slice(&chunk[..self.unpadded_bytes_per_row as usize]);
         }
+        drop(padded_buffer);
+        self.buffer.unmap();
+        Some(rows)
     }
 }